@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Error};
+use pop3_client::{
+  Certificate, ConnectOptions, Fingerprint, Identity, ParseMode, Proxy, Security, TlsConfig, Tunnel,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+  collections::BTreeMap,
+  fs::OpenOptions,
+  io::Write,
+  path::{Path, PathBuf},
+};
+
+use crate::delivery::TargetConfig;
+
+/// how the server certificate of an account is trusted
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsSettings {
+  /// PEM files of certificates trusted in addition to the system roots, for a private CA
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub ca_files: Vec<PathBuf>,
+  /// when not empty, the only server certificates trusted, as for a self-signed certificate
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub pinned_fingerprints: Vec<Fingerprint>,
+  /// client certificate presented to servers requiring mutual TLS
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub client_identity: Option<ClientIdentity>,
+}
+
+/// files of a client certificate with its private key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum ClientIdentity {
+  /// a PEM certificate chain, leaf first, and a PEM PKCS#8 private key
+  Pem {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+  },
+  /// a PKCS#12 archive, as exported by browsers and keychains
+  Pkcs12 {
+    file: PathBuf,
+    #[serde(default)]
+    password: String,
+  },
+}
+
+fn read(path: &Path, what: &str) -> Result<Vec<u8>, Error> {
+  std::fs::read(path).map_err(|err| anyhow!("cannot read {} {}: {}", what, path.display(), err))
+}
+
+impl ClientIdentity {
+  /// the identity, reading its files
+  pub fn load(&self) -> Result<Identity, Error> {
+    match self {
+      Self::Pem {
+        cert_file,
+        key_file,
+      } => Identity::from_pem(
+        &read(cert_file, "client certificate")?,
+        &read(key_file, "client key")?,
+      ),
+      Self::Pkcs12 { file, password } => {
+        Identity::from_pkcs12(&read(file, "client identity")?, password)
+      }
+    }
+  }
+}
+
+impl TlsSettings {
+  /// TLS settings of the library, reading the CA and client identity files
+  pub fn tls_config(&self) -> Result<TlsConfig, Error> {
+    let mut root_certificates = vec![];
+    for path in &self.ca_files {
+      root_certificates.extend(Certificate::from_pem(&read(path, "CA file")?)?);
+    }
+    Ok(TlsConfig {
+      root_certificates,
+      pinned_fingerprints: self.pinned_fingerprints.clone(),
+      identity: self
+        .client_identity
+        .as_ref()
+        .map(ClientIdentity::load)
+        .transpose()?,
+      ..Default::default()
+    })
+  }
+
+  /// trust the certificate with `fingerprint`, returning whether it was not yet
+  pub fn pin(&mut self, fingerprint: Fingerprint) -> bool {
+    if self.pinned_fingerprints.contains(&fingerprint) {
+      return false;
+    }
+    self.pinned_fingerprints.push(fingerprint);
+    true
+  }
+}
+
+/// fingerprints of the server certificates the user trusted when asked, by server address
+///
+/// kept apart from the account file, which only holds the account last logged in with, so that
+/// trusting the certificate of a new server does not replace the account saved for another.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pins(BTreeMap<String, Vec<Fingerprint>>);
+
+impl Pins {
+  /// the pins saved at `path`, none if the file does not exist yet
+  pub fn load(path: &Path) -> Result<Self, Error> {
+    if !path.exists() {
+      return Ok(Self::default());
+    }
+    Ok(serde_json::from_slice(&read(path, "pinned certificates")?)?)
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+    Ok(())
+  }
+
+  /// fingerprints trusted for the server at `addr`
+  pub fn get(&self, addr: &str) -> &[Fingerprint] {
+    self.0.get(addr).map_or(&[], Vec::as_slice)
+  }
+
+  /// trust the certificate with `fingerprint` for `addr`, returning whether it was not yet
+  pub fn pin(&mut self, addr: &str, fingerprint: Fingerprint) -> bool {
+    let pinned = self.0.entry(addr.to_string()).or_default();
+    if pinned.contains(&fingerprint) {
+      return false;
+    }
+    pinned.push(fingerprint);
+    true
+  }
+}
+
+/// connection settings of the account last logged in with, shared by the app and `pop3-cli`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Account {
+  pub addr: String,
+  /// `"tls"`, `"starttls"` or `"plain"`, read from the `with_tls` boolean of older account files
+  #[serde(default, alias = "with_tls", deserialize_with = "security_or_with_tls")]
+  pub security: Security,
+  #[serde(default)]
+  pub lenient: bool,
+  pub name: String,
+  /// only saved when the user asks for it, as the file is not encrypted
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub pass: Option<String>,
+  /// log in with SASL EXTERNAL, relying on `tls.client_identity`, instead of `USER` and `PASS`;
+  /// `name`, if any, is the user to act as
+  #[serde(default)]
+  pub external: bool,
+  #[serde(default)]
+  pub tls: TlsSettings,
+  /// `socks5://` or `http://` URL of a proxy to connect through
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub proxy: Option<String>,
+  /// command to reach the server through instead of connecting, as
+  /// `{"command": "ssh %h nc localhost %p"}`, with `"preauth": true` if it starts logged in
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tunnel: Option<Tunnel>,
+  /// delivery targets the app may fetch mail to, by name, as
+  /// `{"procmail": {"mda": "procmail -d %T"}}`
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub targets: BTreeMap<String, TargetConfig>,
+}
+
+fn security_or_with_tls<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Security, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum SecurityOrWithTls {
+    Security(Security),
+    WithTls(bool),
+  }
+
+  Ok(match SecurityOrWithTls::deserialize(deserializer)? {
+    SecurityOrWithTls::Security(security) => security,
+    SecurityOrWithTls::WithTls(true) => Security::Tls,
+    SecurityOrWithTls::WithTls(false) => Security::Plain,
+  })
+}
+
+fn data_dir() -> Result<PathBuf, Error> {
+  let data_dir = dirs::data_dir().ok_or_else(|| anyhow!("no data directory"))?;
+  Ok(data_dir.join("pop3-client"))
+}
+
+/// default location of the account file
+pub fn account_path() -> Result<PathBuf, Error> {
+  Ok(data_dir()?.join("account.json"))
+}
+
+/// default location of the certificates trusted at login, see [`Pins`]
+pub fn pins_path() -> Result<PathBuf, Error> {
+  Ok(data_dir()?.join("pins.json"))
+}
+
+/// location of the unique-ids of the mails fetched and kept on the server for `name` at `addr`,
+/// see [`crate::delivery::FetchIds`]
+pub fn fetchids_path(addr: &str, name: &str) -> Result<PathBuf, Error> {
+  let file: String = format!("{}@{}", name, addr)
+    .chars()
+    .map(|c| match c {
+      'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '@' => c,
+      _ => '_',
+    })
+    .collect();
+  Ok(data_dir()?.join("fetchids").join(file))
+}
+
+impl Account {
+  /// the account in `path` if it is for `addr`, so that its settings apply, or a new one
+  pub fn saved_for(path: &Path, addr: &str) -> Result<Self, Error> {
+    if path.exists() {
+      let account = Self::load(path)?;
+      if account.addr == addr {
+        return Ok(account);
+      }
+    }
+    Ok(Self {
+      addr: addr.to_string(),
+      ..Default::default()
+    })
+  }
+
+  /// how to connect to the server of the account
+  pub fn connect_options(&self) -> Result<ConnectOptions, Error> {
+    Ok(ConnectOptions {
+      security: self.security,
+      mode: if self.lenient {
+        ParseMode::Lenient
+      } else {
+        ParseMode::Strict
+      },
+      tls: self.tls.tls_config()?,
+      proxy: self.proxy_config()?,
+      tunnel: self.tunnel.clone(),
+    })
+  }
+
+  /// the proxy of the library, parsed from `proxy`
+  pub fn proxy_config(&self) -> Result<Option<Proxy>, Error> {
+    self.proxy.as_deref().map(str::parse).transpose()
+  }
+
+  pub fn load(path: &Path) -> Result<Self, Error> {
+    Ok(serde_json::from_slice(&read(path, "account file")?)?)
+  }
+
+  /// write the account to `path`, readable by the user only as it may hold the password
+  pub fn save(&self, path: &Path) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // a file saved before keeps its permissions when opened
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(&serde_json::to_vec_pretty(self)?)?;
+    Ok(())
+  }
+}
+
+#[test]
+fn test_account_round_trip() {
+  let path = std::env::temp_dir()
+    .join(format!("pop3-client-test-account-{}", std::process::id()))
+    .join("account.json");
+  let account = Account {
+    addr: "pop.example.com:110".to_string(),
+    security: Security::StartTls,
+    lenient: false,
+    name: "alice".to_string(),
+    pass: None,
+    external: true,
+    tls: TlsSettings {
+      ca_files: vec![PathBuf::from("/etc/ssl/private-ca.pem")],
+      pinned_fingerprints: vec![Fingerprint([0xab; 32])],
+      client_identity: Some(ClientIdentity::Pkcs12 {
+        file: PathBuf::from("alice.p12"),
+        password: "secret".to_string(),
+      }),
+    },
+    proxy: Some("socks5://proxy.example.com:1080".to_string()),
+    tunnel: Some(Tunnel {
+      command: "ssh %h dovecot --exec-mail pop3".to_string(),
+      preauth: true,
+    }),
+    targets: [(
+      "procmail".to_string(),
+      TargetConfig::Mda("procmail -d %T".to_string()),
+    )]
+    .into_iter()
+    .collect(),
+  };
+  account.save(&path).unwrap();
+  assert!(!std::fs::read_to_string(&path).unwrap().contains("\"pass\""));
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+  }
+  assert_eq!(Account::load(&path).unwrap(), account);
+  assert_eq!(
+    Account::saved_for(&path, "pop.example.com:110").unwrap(),
+    account
+  );
+  assert_eq!(
+    Account::saved_for(&path, "pop.example.org:995").unwrap(),
+    Account {
+      addr: "pop.example.org:995".to_string(),
+      ..Default::default()
+    }
+  );
+  assert!(std::fs::read_to_string(&path)
+    .unwrap()
+    .contains("\"security\": \"starttls\""));
+  assert!(account.proxy_config().unwrap().is_some());
+  std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_account_with_tls() {
+  // account files saved before `security` only tell TLS from plain text
+  for (with_tls, security) in [(true, Security::Tls), (false, Security::Plain)] {
+    let json = format!(
+      r#"{{"addr": "pop.example.com", "with_tls": {}, "name": "alice"}}"#,
+      with_tls
+    );
+    let account: Account = serde_json::from_str(&json).unwrap();
+    assert_eq!(account.security, security);
+    assert_eq!(account.connect_options().unwrap().security, security);
+  }
+}
+
+#[test]
+fn test_pins() {
+  let dir = std::env::temp_dir().join(format!("pop3-client-test-pins-{}", std::process::id()));
+  let account_path = dir.join("account.json");
+  let pins_path = dir.join("pins.json");
+  let account = Account {
+    addr: "pop.example.com:995".to_string(),
+    name: "alice".to_string(),
+    pass: Some("secret".to_string()),
+    ..Default::default()
+  };
+  account.save(&account_path).unwrap();
+
+  // trusting another server leaves the saved account alone
+  let mut pins = Pins::load(&pins_path).unwrap();
+  assert!(pins.pin("pop.example.org:995", Fingerprint([0xab; 32])));
+  assert!(!pins.pin("pop.example.org:995", Fingerprint([0xab; 32])));
+  pins.save(&pins_path).unwrap();
+  assert_eq!(Account::load(&account_path).unwrap(), account);
+
+  let pins = Pins::load(&pins_path).unwrap();
+  assert_eq!(pins.get("pop.example.org:995"), [Fingerprint([0xab; 32])]);
+  assert!(pins.get("pop.example.com:995").is_empty());
+  std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,711 @@
+use anyhow::{anyhow, Error};
+use pop3_client::{Pop3, ServerAddress};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet},
+  fs::OpenOptions,
+  io::{ErrorKind, Write},
+  path::{Path, PathBuf},
+  process::Stdio,
+  sync::atomic::{AtomicU64, Ordering},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufStream},
+  net::TcpStream,
+  process::Command,
+};
+
+/// number of deliveries by this process, keeping filenames unique within the same microsecond
+static DELIVERIES: AtomicU64 = AtomicU64::new(0);
+
+fn hostname() -> String {
+  let hostname = std::env::var("HOSTNAME")
+    .or_else(|_| std::env::var("COMPUTERNAME"))
+    .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+    .unwrap_or_default();
+  match hostname.trim() {
+    "" => "localhost".to_string(),
+    hostname => hostname.to_string(),
+  }
+}
+
+/// a filename unique across processes and hosts, as described in the Maildir specification
+fn unique_name() -> String {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default();
+  format!(
+    "{}.M{}P{}Q{}.{}",
+    now.as_secs(),
+    now.subsec_micros(),
+    std::process::id(),
+    DELIVERIES.fetch_add(1, Ordering::Relaxed),
+    // `/` and `:` cannot appear in the filename, the latter separating the info part
+    hostname().replace('/', "\\057").replace(':', "\\072")
+  )
+}
+
+/// what the fetch loop knows about a mail besides its content
+pub struct Envelope<'a> {
+  /// local recipient, recorded as `Delivered-To:`
+  pub recipient: &'a str,
+  /// unique-id from UIDL, recorded as `X-POP3-UIDL:`
+  pub unique_id: Option<&'a str>,
+}
+
+/// `key: value` header line, ending like the first line of `mail`
+fn header_line(mail: &[u8], key: &str, value: &str) -> String {
+  let crlf = match memchr::memchr(b'\n', mail) {
+    Some(pos) => pos > 0 && mail[pos - 1] == b'\r',
+    None => true,
+  };
+  // a line break in the value would inject another header
+  let value: String = value.chars().filter(|c| !c.is_control()).collect();
+  format!("{}: {}{}", key, value, if crlf { "\r\n" } else { "\n" })
+}
+
+/// fail unless `recipient` is safe to pass to an MDA as an argument and to an LMTP server in
+/// `RCPT TO:<…>`, as it may come from the login name
+///
+/// an empty recipient would be an empty `%T` to the MDA and the null path `<>` to LMTP, which is
+/// not a valid recipient.
+fn check_recipient(recipient: &str) -> Result<(), Error> {
+  if recipient.is_empty() {
+    return Err(anyhow!("no recipient to deliver to"));
+  }
+  if recipient.starts_with('-')
+    || recipient
+      .chars()
+      .any(|c| c.is_control() || "<>".contains(c))
+  {
+    return Err(anyhow!("invalid recipient {:?}", recipient));
+  }
+  Ok(())
+}
+
+/// `mail` with the headers recording `envelope` prepended
+fn with_headers(mail: &[u8], envelope: &Envelope) -> Vec<u8> {
+  let mut headers = header_line(mail, "Delivered-To", envelope.recipient);
+  if let Some(unique_id) = envelope.unique_id {
+    headers += &header_line(mail, "X-POP3-UIDL", unique_id);
+  }
+  let mut content = headers.into_bytes();
+  content.extend_from_slice(mail);
+  content
+}
+
+pub struct Maildir {
+  dir: PathBuf,
+}
+
+impl Maildir {
+  /// open the Maildir at `dir`, creating its `tmp`, `new` and `cur` subdirectories if needed
+  pub fn open(dir: &Path) -> Result<Self, Error> {
+    for subdir in ["tmp", "new", "cur"] {
+      std::fs::create_dir_all(dir.join(subdir))?;
+    }
+    Ok(Self {
+      dir: dir.to_path_buf(),
+    })
+  }
+
+  /// write `mail` to `tmp`, then move it to `new` once it is safely on disk, returning its final
+  /// path
+  pub fn deliver(&self, mail: &[u8], envelope: &Envelope) -> Result<PathBuf, Error> {
+    let name = unique_name();
+    let tmp = self.dir.join("tmp").join(&name);
+    let new = self.dir.join("new").join(&name);
+    let write = || -> Result<(), Error> {
+      let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+      file.write_all(&with_headers(mail, envelope))?;
+      file.sync_all()?;
+      Ok(())
+    };
+    if let Err(err) = write().and_then(|_| Ok(std::fs::rename(&tmp, &new)?)) {
+      let _ = std::fs::remove_file(&tmp);
+      return Err(err);
+    }
+    Ok(new)
+  }
+}
+
+/// a mail delivery agent such as procmail or maildrop, run once per mail with the mail on stdin
+pub struct Mda {
+  program: String,
+  args: Vec<String>,
+}
+
+impl Mda {
+  /// `command` is split on whitespace and run without a shell, with `%T` in its arguments
+  /// replaced by the recipient
+  pub fn new(command: &str) -> Result<Self, Error> {
+    let mut words = command.split_whitespace().map(str::to_string);
+    Ok(Self {
+      program: words.next().ok_or_else(|| anyhow!("empty mda command"))?,
+      args: words.collect(),
+    })
+  }
+
+  /// run the MDA, failing unless it exits successfully
+  pub async fn deliver(&self, mail: &[u8], envelope: &Envelope<'_>) -> Result<(), Error> {
+    check_recipient(envelope.recipient)?;
+    let mut child = Command::new(&self.program)
+      .args(
+        self
+          .args
+          .iter()
+          .map(|arg| arg.replace("%T", envelope.recipient)),
+      )
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .spawn()?;
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("no mda stdin"))?;
+    let content = with_headers(mail, envelope);
+    // write while waiting, so an MDA filling its stderr before reading stdin cannot deadlock
+    let (written, output) = tokio::join!(
+      async move {
+        stdin.write_all(&content).await?;
+        stdin.shutdown().await
+      },
+      child.wait_with_output()
+    );
+    let output = output?;
+    if !output.status.success() {
+      return Err(anyhow!(
+        "{} exited with {}: {}",
+        self.program,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+      ));
+    }
+    written?;
+    Ok(())
+  }
+}
+
+/// default port of LMTP over TCP
+const LMTP_PORT: u16 = 24;
+
+trait Socket: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T> Socket for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+/// an LMTP client (RFC 2033) delivering to a single recipient per mail
+pub struct Lmtp {
+  stream: BufStream<Box<dyn Socket>>,
+}
+
+/// `mail` as the content of DATA: dot-stuffed, with CRLF line endings and the terminating dot
+fn lmtp_data(mail: &[u8]) -> Vec<u8> {
+  let mut data = Vec::with_capacity(mail.len() + 5);
+  let mail = mail.strip_suffix(b"\n").unwrap_or(mail);
+  for line in mail.split(|&c| c == b'\n') {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.starts_with(b".") {
+      data.push(b'.');
+    }
+    data.extend_from_slice(line);
+    data.extend_from_slice(b"\r\n");
+  }
+  data.extend_from_slice(b".\r\n");
+  data
+}
+
+impl Lmtp {
+  /// connect to the LMTP server at a Unix socket path, or at `host[:port]` over TCP, with IPv6
+  /// addresses bracketed
+  pub async fn connect(addr: &str) -> Result<Self, Error> {
+    let socket: Box<dyn Socket> = if addr.starts_with('/') {
+      #[cfg(unix)]
+      {
+        Box::new(tokio::net::UnixStream::connect(addr).await?)
+      }
+      #[cfg(not(unix))]
+      return Err(anyhow!("unix sockets are not supported on this platform"));
+    } else {
+      let addr: ServerAddress = addr.parse()?;
+      let port = addr.port.unwrap_or(LMTP_PORT);
+      Box::new(TcpStream::connect((addr.host.as_str(), port)).await?)
+    };
+    let mut lmtp = Self {
+      stream: BufStream::new(socket),
+    };
+    lmtp.reply(2).await?;
+    lmtp.command(&format!("LHLO {}", hostname()), 2).await?;
+    Ok(lmtp)
+  }
+
+  /// read a possibly multiline reply, failing unless its code is in the class `expect`xx
+  async fn reply(&mut self, expect: u16) -> Result<String, Error> {
+    let mut text = String::new();
+    loop {
+      let mut line = String::new();
+      if self.stream.read_line(&mut line).await? == 0 {
+        return Err(anyhow!("connection closed by remote"));
+      }
+      let line = line.trim_end();
+      let code = line
+        .get(..3)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| anyhow!("malformed lmtp reply: {:?}", line))?;
+      text += line.get(4..).unwrap_or("");
+      if line.as_bytes().get(3) == Some(&b'-') {
+        text.push('\n');
+        continue;
+      }
+      if code / 100 != expect {
+        return Err(anyhow!("{}", line));
+      }
+      return Ok(text);
+    }
+  }
+
+  async fn command(&mut self, line: &str, expect: u16) -> Result<String, Error> {
+    self.stream.write_all(line.as_bytes()).await?;
+    self.stream.write_all(b"\r\n").await?;
+    self.stream.flush().await?;
+    self.reply(expect).await
+  }
+
+  async fn transaction(&mut self, mail: &[u8], envelope: &Envelope<'_>) -> Result<(), Error> {
+    check_recipient(envelope.recipient)?;
+    self.command("MAIL FROM:<>", 2).await?;
+    self
+      .command(&format!("RCPT TO:<{}>", envelope.recipient), 2)
+      .await?;
+    self.command("DATA", 3).await?;
+    self
+      .stream
+      .write_all(&lmtp_data(&with_headers(mail, envelope)))
+      .await?;
+    self.stream.flush().await?;
+    // one reply per accepted recipient
+    self.reply(2).await?;
+    Ok(())
+  }
+
+  pub async fn deliver(&mut self, mail: &[u8], envelope: &Envelope<'_>) -> Result<(), Error> {
+    let res = self.transaction(mail, envelope).await;
+    if res.is_err() {
+      // leave the session ready for the next mail
+      let _ = self.command("RSET", 2).await;
+    }
+    res
+  }
+
+  pub async fn quit(mut self) -> Result<(), Error> {
+    self.command("QUIT", 2).await?;
+    Ok(())
+  }
+}
+
+/// where a fetch session delivers mail, as configured by the user
+///
+/// MDA commands and LMTP servers run with the rights of the user, so the app only takes them from
+/// the account file, by name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetConfig {
+  /// directory receiving one unmodified `.eml` file per mail
+  Directory(PathBuf),
+  /// path of the Maildir
+  Maildir(PathBuf),
+  /// MDA command line
+  Mda(String),
+  /// Unix socket path or `host[:port]` of the LMTP server
+  Lmtp(String),
+}
+
+pub enum Target {
+  Directory(PathBuf),
+  Maildir(Maildir),
+  Mda(Mda),
+  Lmtp(Lmtp),
+}
+
+impl Target {
+  pub async fn open(config: &TargetConfig) -> Result<Self, Error> {
+    Ok(match config {
+      TargetConfig::Directory(dir) => {
+        std::fs::create_dir_all(dir)?;
+        Self::Directory(dir.clone())
+      }
+      TargetConfig::Maildir(dir) => Self::Maildir(Maildir::open(dir)?),
+      TargetConfig::Mda(command) => Self::Mda(Mda::new(command)?),
+      TargetConfig::Lmtp(addr) => Self::Lmtp(Lmtp::connect(addr).await?),
+    })
+  }
+
+  pub async fn deliver(&mut self, mail: &[u8], envelope: &Envelope<'_>) -> Result<(), Error> {
+    match self {
+      Self::Directory(dir) => {
+        // name the file after the unique-id when there is one, so fetching again overwrites it
+        let name = match envelope.unique_id {
+          Some(unique_id) => unique_id
+            .chars()
+            .map(|c| match c {
+              'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+              _ => '_',
+            })
+            .collect(),
+          None => unique_name(),
+        };
+        Ok(std::fs::write(dir.join(format!("{}.eml", name)), mail)?)
+      }
+      Self::Maildir(maildir) => maildir.deliver(mail, envelope).map(|_| ()),
+      Self::Mda(mda) => mda.deliver(mail, envelope).await,
+      Self::Lmtp(lmtp) => lmtp.deliver(mail, envelope).await,
+    }
+  }
+
+  /// end the delivery session, if the target has one
+  pub async fn close(self) -> Result<(), Error> {
+    match self {
+      Self::Lmtp(lmtp) => lmtp.quit().await,
+      _ => Ok(()),
+    }
+  }
+}
+
+/// unique-ids of the mails of an account already fetched and kept on the server, so that the next
+/// fetch skips them, as the `.fetchids` file of fetchmail
+pub struct FetchIds {
+  path: PathBuf,
+  ids: HashSet<String>,
+}
+
+impl FetchIds {
+  /// the unique-ids saved at `path`, none if the file does not exist yet
+  pub fn load(path: &Path) -> Result<Self, Error> {
+    let ids = match std::fs::read_to_string(path) {
+      Ok(ids) => ids.lines().map(str::to_string).collect(),
+      Err(err) if err.kind() == ErrorKind::NotFound => HashSet::new(),
+      Err(err) => return Err(err.into()),
+    };
+    Ok(Self {
+      path: path.to_path_buf(),
+      ids,
+    })
+  }
+
+  pub fn contains(&self, unique_id: &str) -> bool {
+    self.ids.contains(unique_id)
+  }
+
+  /// write the unique-ids one per line, replacing the file at once so that a failure cannot leave
+  /// it half written
+  fn save(&self) -> Result<(), Error> {
+    if let Some(dir) = self.path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let mut ids: Vec<_> = self.ids.iter().map(String::as_str).collect();
+    ids.sort_unstable();
+    let tmp = self.path.with_extension("tmp");
+    std::fs::write(
+      &tmp,
+      ids.iter().map(|id| format!("{}\n", id)).collect::<String>(),
+    )?;
+    std::fs::rename(&tmp, &self.path)?;
+    Ok(())
+  }
+}
+
+/// retrieve every mail in the maildrop not in `fetched` and deliver it to `target`, deleting it
+/// from the server after a successful delivery unless `keep` is set, in which case its unique-id
+/// is added to `fetched`
+///
+/// mails already in `fetched` are deleted without `keep`, as they have been delivered before.
+/// Returns the message numbers of the delivered mails.
+pub async fn fetch(
+  pop3: &mut Pop3,
+  target: &mut Target,
+  recipient: &str,
+  keep: bool,
+  fetched: &mut FetchIds,
+) -> Result<Vec<u64>, Error> {
+  let (list, _) = pop3.list(None).await?;
+  // UIDL is optional, so servers without it only miss the `X-POP3-UIDL:` header, and every mail
+  // is fetched again with `keep`
+  let unique_ids: HashMap<u64, String> = match pop3.uidl(None).await {
+    Ok((unique_ids, _)) => unique_ids.into_iter().collect(),
+    Err(_) => HashMap::new(),
+  };
+  if !unique_ids.is_empty() {
+    // forget the mails no longer on the server
+    let listed: HashSet<&String> = unique_ids.values().collect();
+    let len = fetched.ids.len();
+    fetched.ids.retain(|id| listed.contains(id));
+    if fetched.ids.len() != len {
+      fetched.save()?;
+    }
+  }
+  let mut delivered = vec![];
+  for (id, _) in list {
+    let unique_id = unique_ids.get(&id).map(String::as_str);
+    if unique_id.is_some_and(|unique_id| fetched.contains(unique_id)) {
+      if !keep {
+        pop3.dele(id).await?;
+      }
+      continue;
+    }
+    let (mail, _) = pop3.retr(id).await?;
+    let envelope = Envelope {
+      recipient,
+      unique_id,
+    };
+    target
+      .deliver(&mail, &envelope)
+      .await
+      .map_err(|err| anyhow!("failed to deliver message {}: {}", id, err))?;
+    match (keep, unique_id) {
+      // saved right away, so that an interrupted fetch does not deliver it again
+      (true, Some(unique_id)) => {
+        fetched.ids.insert(unique_id.to_string());
+        fetched.save()?;
+      }
+      (true, None) => {}
+      (false, _) => {
+        pop3.dele(id).await?;
+      }
+    }
+    delivered.push(id);
+  }
+  Ok(delivered)
+}
+
+#[test]
+fn test_header_line() {
+  assert_eq!(header_line(b"a: b\r\n", "X-A", "1"), "X-A: 1\r\n");
+  assert_eq!(header_line(b"a: b\n", "X-A", "1"), "X-A: 1\n");
+  assert_eq!(
+    header_line(b"a: b\r\n", "X-A", "1\r\nBcc: x"),
+    "X-A: 1Bcc: x\r\n"
+  );
+}
+
+#[tokio::test]
+async fn test_fetch_to_maildir() -> Result<(), Error> {
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+  };
+
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr = listener.local_addr()?.to_string();
+  let server = tokio::spawn(async move {
+    let (mut socket, _) = listener.accept().await?;
+    let mut commands = vec![];
+    socket.write_all(b"+OK\r\n").await?;
+    for reply in [
+      &b"+OK\r\n1 20\r\n2 20\r\n.\r\n"[..],
+      b"+OK\r\n1 uid-1\r\n2 uid-2\r\n.\r\n",
+      b"+OK\r\nSubject: one\r\n\r\n1\r\n.\r\n",
+      b"+OK\r\n",
+      b"+OK\r\nSubject: two\r\n\r\n2\r\n.\r\n",
+      b"+OK\r\n",
+      b"+OK\r\n",
+    ] {
+      let mut buf = [0u8; 64];
+      let len = socket.read(&mut buf).await?;
+      commands.push(String::from_utf8_lossy(&buf[..len]).to_string());
+      socket.write_all(reply).await?;
+    }
+    Ok::<_, std::io::Error>(commands)
+  });
+
+  let dir = std::env::temp_dir().join(format!("pop3-client-test-maildir-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  let mut target = Target::open(&TargetConfig::Maildir(dir.clone())).await?;
+
+  let mut fetched = FetchIds::load(&dir.join("fetchids"))?;
+  let (mut pop3, _) = Pop3::new(&addr, false).await?;
+  let delivered = fetch(
+    &mut pop3,
+    &mut target,
+    "alice@example.com",
+    false,
+    &mut fetched,
+  )
+  .await?;
+  pop3.quit().await?;
+
+  assert_eq!(
+    server.await??,
+    [
+      "LIST\r\n",
+      "UIDL\r\n",
+      "RETR 1\r\n",
+      "DELE 1\r\n",
+      "RETR 2\r\n",
+      "DELE 2\r\n",
+      "QUIT\r\n"
+    ]
+  );
+  assert_eq!(delivered, [1, 2]);
+  let mut paths = std::fs::read_dir(dir.join("new"))?
+    .map(|entry| Ok(entry?.path()))
+    .collect::<Result<Vec<_>, Error>>()?;
+  paths.sort();
+  assert_eq!(
+    std::fs::read(&paths[0])?,
+    b"Delivered-To: alice@example.com\r\nX-POP3-UIDL: uid-1\r\nSubject: one\r\n\r\n1\r\n"
+  );
+  assert_eq!(std::fs::read_dir(dir.join("tmp"))?.count(), 0);
+  assert!(!dir.join("fetchids").exists());
+
+  std::fs::remove_dir_all(&dir)?;
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_kept() -> Result<(), Error> {
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+  };
+
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr = listener.local_addr()?.to_string();
+  let server = tokio::spawn(async move {
+    let (mut socket, _) = listener.accept().await?;
+    let mut commands = vec![];
+    socket.write_all(b"+OK\r\n").await?;
+    for reply in [
+      &b"+OK\r\n1 20\r\n2 20\r\n.\r\n"[..],
+      b"+OK\r\n1 uid-1\r\n2 uid-2\r\n.\r\n",
+      b"+OK\r\nSubject: two\r\n\r\n2\r\n.\r\n",
+      b"+OK\r\n",
+    ] {
+      let mut buf = [0u8; 64];
+      let len = socket.read(&mut buf).await?;
+      commands.push(String::from_utf8_lossy(&buf[..len]).to_string());
+      socket.write_all(reply).await?;
+    }
+    Ok::<_, std::io::Error>(commands)
+  });
+
+  let dir = std::env::temp_dir().join(format!("pop3-client-test-kept-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir)?;
+  let path = dir.join("fetchids");
+  // uid-0 was deleted from the server since
+  std::fs::write(&path, "uid-0\nuid-1\n")?;
+  let mut fetched = FetchIds::load(&path)?;
+  let mut target = Target::open(&TargetConfig::Directory(dir.clone())).await?;
+
+  let (mut pop3, _) = Pop3::new(&addr, false).await?;
+  let delivered = fetch(&mut pop3, &mut target, "alice", true, &mut fetched).await?;
+  pop3.quit().await?;
+
+  assert_eq!(
+    server.await??,
+    ["LIST\r\n", "UIDL\r\n", "RETR 2\r\n", "QUIT\r\n"]
+  );
+  assert_eq!(delivered, [2]);
+  assert_eq!(std::fs::read_to_string(&path)?, "uid-1\nuid-2\n");
+  assert!(dir.join("uid-2.eml").exists());
+
+  std::fs::remove_dir_all(&dir)?;
+  Ok(())
+}
+
+#[test]
+fn test_check_recipient() {
+  assert!(check_recipient("alice@example.com").is_ok());
+  assert!(check_recipient("").is_err());
+  assert!(check_recipient("alice>\r\nDATA").is_err());
+  assert!(check_recipient("alice> NOTIFY=NEVER").is_err());
+  assert!(check_recipient("--rcfile=/tmp/x").is_err());
+}
+
+#[test]
+fn test_lmtp_data() {
+  assert_eq!(lmtp_data(b"a\n.b\n..\n"), b"a\r\n..b\r\n...\r\n.\r\n");
+  assert_eq!(lmtp_data(b"a\r\n\r\nb"), b"a\r\n\r\nb\r\n.\r\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_mda() -> Result<(), Error> {
+  let envelope = Envelope {
+    recipient: "alice",
+    unique_id: None,
+  };
+  assert!(Mda::new("false")?
+    .deliver(b"\r\n", &envelope)
+    .await
+    .is_err());
+
+  let path = std::env::temp_dir().join(format!("pop3-client-test-mda-{}", std::process::id()));
+  // `%T` is substituted as a whole argument, so it cannot inject one
+  let mda = Mda::new(&format!("tee {}-%T", path.display()))?;
+  mda.deliver(b"Subject: hi\r\n\r\n", &envelope).await?;
+  let delivered = path.with_file_name(format!(
+    "{}-alice",
+    path.file_name().unwrap().to_string_lossy()
+  ));
+  assert_eq!(
+    std::fs::read(&delivered)?,
+    b"Delivered-To: alice\r\nSubject: hi\r\n\r\n"
+  );
+  std::fs::remove_file(&delivered)?;
+  Ok(())
+}
+
+#[tokio::test]
+async fn test_lmtp() -> Result<(), Error> {
+  use tokio::{io::AsyncReadExt, net::TcpListener};
+
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr = listener.local_addr()?.to_string();
+  let server = tokio::spawn(async move {
+    let (mut socket, _) = listener.accept().await?;
+    let mut received = vec![];
+    socket.write_all(b"220 ready\r\n").await?;
+    for reply in [
+      &b"250-localhost\r\n250 PIPELINING\r\n"[..],
+      b"250 ok\r\n",
+      b"550 no such user\r\n",
+      b"250 ok\r\n",
+      b"250 ok\r\n",
+      b"250 ok\r\n",
+      b"354 go ahead\r\n",
+      b"250 delivered\r\n",
+      b"221 bye\r\n",
+    ] {
+      let mut buf = [0u8; 256];
+      let len = socket.read(&mut buf).await?;
+      received.push(String::from_utf8_lossy(&buf[..len]).to_string());
+      socket.write_all(reply).await?;
+    }
+    Ok::<_, std::io::Error>(received)
+  });
+
+  let mut lmtp = Lmtp::connect(&addr).await?;
+  let mut envelope = Envelope {
+    recipient: "nobody",
+    unique_id: None,
+  };
+  let err = lmtp.deliver(b"\r\n", &envelope).await.unwrap_err();
+  assert_eq!(err.to_string(), "550 no such user");
+  envelope.recipient = "alice";
+  lmtp.deliver(b"Subject: hi\r\n\r\n.\r\n", &envelope).await?;
+  lmtp.quit().await?;
+
+  let received = server.await??;
+  assert_eq!(
+    received[1..],
+    [
+      "MAIL FROM:<>\r\n",
+      "RCPT TO:<nobody>\r\n",
+      "RSET\r\n",
+      "MAIL FROM:<>\r\n",
+      "RCPT TO:<alice>\r\n",
+      "DATA\r\n",
+      "Delivered-To: alice\r\nSubject: hi\r\n\r\n..\r\n.\r\n",
+      "QUIT\r\n",
+    ]
+  );
+  Ok(())
+}
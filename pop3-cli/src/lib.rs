@@ -0,0 +1,5 @@
+//! the account file and mail delivery shared by the app and `pop3-cli`, which build without the
+//! GUI toolkit
+
+pub mod account;
+pub mod delivery;
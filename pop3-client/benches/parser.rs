@@ -0,0 +1,209 @@
+use bytes::BytesMut;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pop3_client::{
+  parser::{self, Expect, ResponseDecoder},
+  ParseMode,
+};
+use tokio_util::codec::Decoder;
+
+/// size of the reads the decoder is fed with in the chunked benchmarks
+const CHUNK: usize = 8 * 1024;
+
+fn retr_response(size: usize) -> Vec<u8> {
+  let mut msg = b"+OK message follows\r\n".to_vec();
+  let mut line = 0;
+  while msg.len() < size {
+    // every tenth line is dot-stuffed
+    if line % 10 == 0 {
+      msg.extend_from_slice(b"..");
+    }
+    msg.extend_from_slice(&[b'a' + (line % 26) as u8; 74]);
+    msg.extend_from_slice(b"\r\n");
+    line += 1;
+  }
+  msg.extend_from_slice(b".\r\n");
+  msg
+}
+
+fn list_response(listings: u64) -> Vec<u8> {
+  let mut msg = format!("+OK {} messages\r\n", listings).into_bytes();
+  for id in 1..=listings {
+    msg.extend_from_slice(format!("{} {}\r\n", id, id * 1021).as_bytes());
+  }
+  msg.extend_from_slice(b".\r\n");
+  msg
+}
+
+/// the parsers before `ResponseDecoder`, in strict mode, kept as the baseline of the benchmarks:
+/// the response is read line by line into an owned buffer until the terminator, then parsed whole
+mod baseline {
+  use anyhow::{anyhow, Result};
+  use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_until},
+    character::complete::{char, crlf, digit1, space0},
+    combinator::{map_res, opt, recognize, value},
+    sequence::{pair, separated_pair, terminated},
+    IResult,
+  };
+  use std::io::BufRead;
+
+  type Parsed<'a, T> = IResult<&'a [u8], Result<T, &'a [u8]>>;
+  type ScanListings<'a> = (Vec<(u64, u64)>, &'a [u8]);
+
+  fn get_u64(msg: &[u8]) -> IResult<&[u8], u64> {
+    map_res(map_res(digit1, std::str::from_utf8), str::parse::<u64>)(msg)
+  }
+
+  fn get_status_line(msg: &[u8]) -> Parsed<'_, &[u8]> {
+    let (msg, (ok, status_msg)) = pair(
+      alt((value(true, tag("+OK")), value(false, tag("-ERR")))),
+      terminated(take_until("\r\n"), crlf),
+    )(msg)?;
+    let (status_msg, _) = space0(status_msg)?;
+    Ok((msg, if ok { Ok(status_msg) } else { Err(status_msg) }))
+  }
+
+  /// a line of a multi-line response with its line ending and without dot-stuffing, `None` for
+  /// the terminator
+  fn get_line_with_crlf(msg: &[u8]) -> IResult<&[u8], Option<&[u8]>> {
+    let (msg, (octet, line)) =
+      pair(opt(char('.')), recognize(pair(take_until("\r\n"), crlf)))(msg)?;
+    Ok((msg, (octet.is_none() || line != b"\r\n").then_some(line)))
+  }
+
+  fn get_retr_result(msg: &[u8]) -> Parsed<'_, (Vec<u8>, &[u8])> {
+    let (mut rest, retr) = get_status_line(msg)?;
+    match retr {
+      Ok(msg) => {
+        let mut mail = Vec::<u8>::new();
+        loop {
+          let (new_rest, line) = get_line_with_crlf(rest)?;
+          rest = new_rest;
+          match line {
+            Some(line) => mail.extend_from_slice(line),
+            None => break Ok((rest, Ok((mail, msg)))),
+          }
+        }
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+
+  fn get_list_result(msg: &[u8]) -> Parsed<'_, ScanListings<'_>> {
+    let (mut rest, list) = get_status_line(msg)?;
+    match list {
+      Ok(msg) => {
+        let mut list = vec![];
+        loop {
+          let (new_rest, line) = get_line_with_crlf(rest)?;
+          rest = new_rest;
+          match line {
+            Some(line) => list.push(separated_pair(get_u64, char(' '), get_u64)(line)?.1),
+            None => break Ok((rest, Ok((list, msg)))),
+          }
+        }
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+
+  pub fn parse_retr_result(msg: Vec<u8>) -> Result<(Vec<u8>, String)> {
+    match get_retr_result(&msg) {
+      Ok((_, Ok((mail, ok_msg)))) => Ok((mail, String::from_utf8_lossy(ok_msg).to_string())),
+      Ok((_, Err(msg))) => Err(anyhow! {String::from_utf8_lossy(msg).to_string()}),
+      Err(err) => Err(anyhow! {err.to_string()}),
+    }
+  }
+
+  pub fn parse_list(msg: Vec<u8>) -> Result<(Vec<(u64, u64)>, String)> {
+    match get_list_result(&msg) {
+      Ok((_, Ok((list, ok_msg)))) => Ok((list, String::from_utf8_lossy(ok_msg).to_string())),
+      Ok((_, Err(msg))) => Err(anyhow! {String::from_utf8_lossy(msg).to_string()}),
+      Err(err) => Err(anyhow! {err.to_string()}),
+    }
+  }
+
+  /// read `msg` line by line until the terminator of a multi-line response, then `parse` it
+  pub fn read_until<T>(msg: &[u8], chunk: usize, parse: impl Fn(Vec<u8>) -> Result<T>) -> T {
+    let mut reader = std::io::BufReader::with_capacity(chunk, msg);
+    let mut buf = Vec::<u8>::new();
+    loop {
+      if reader.read_until(b'\n', &mut buf).unwrap() == 0 {
+        panic!("incomplete response")
+      }
+      if buf.ends_with(b"\r\n.\r\n") {
+        break parse(buf).unwrap();
+      }
+    }
+  }
+}
+
+fn decode(msg: &[u8], expect: Expect, chunk: usize) -> parser::Response {
+  let mut decoder = ResponseDecoder::new(ParseMode::Strict);
+  decoder.expect(expect);
+  let mut buf = BytesMut::with_capacity(msg.len());
+  for chunk in msg.chunks(chunk) {
+    buf.extend_from_slice(chunk);
+    if let Some(response) = decoder.decode(&mut buf).unwrap() {
+      return response;
+    }
+  }
+  panic!("incomplete response")
+}
+
+fn bench_retr(c: &mut Criterion) {
+  let mut group = c.benchmark_group("retr");
+  for size in [4 * 1024, 64 * 1024, 1024 * 1024] {
+    let msg = retr_response(size);
+    // both read paths give the same mail
+    match decode(&msg, Expect::Mail, CHUNK) {
+      parser::Response::Mail(mail, _) => assert_eq!(
+        baseline::read_until(&msg, CHUNK, baseline::parse_retr_result).0,
+        mail
+      ),
+      response => panic!("unexpected response {:?}", response),
+    }
+    group.throughput(Throughput::Bytes(msg.len() as u64));
+    group.bench_with_input(BenchmarkId::new("read_until", size), &msg, |b, msg| {
+      b.iter(|| baseline::read_until(black_box(msg), CHUNK, baseline::parse_retr_result))
+    });
+    group.bench_with_input(BenchmarkId::new("decoder", size), &msg, |b, msg| {
+      b.iter(|| decode(black_box(msg), Expect::Mail, msg.len()))
+    });
+    group.bench_with_input(BenchmarkId::new("decoder_chunked", size), &msg, |b, msg| {
+      b.iter(|| decode(black_box(msg), Expect::Mail, CHUNK))
+    });
+  }
+  group.finish();
+}
+
+fn bench_list(c: &mut Criterion) {
+  let mut group = c.benchmark_group("list");
+  for listings in [10, 1000, 100000] {
+    let msg = list_response(listings);
+    match decode(&msg, Expect::ScanListings, CHUNK) {
+      parser::Response::ScanListings(list, _) => assert_eq!(
+        baseline::read_until(&msg, CHUNK, baseline::parse_list).0,
+        list
+      ),
+      response => panic!("unexpected response {:?}", response),
+    }
+    group.throughput(Throughput::Bytes(msg.len() as u64));
+    group.bench_with_input(BenchmarkId::new("read_until", listings), &msg, |b, msg| {
+      b.iter(|| baseline::read_until(black_box(msg), CHUNK, baseline::parse_list))
+    });
+    group.bench_with_input(BenchmarkId::new("decoder", listings), &msg, |b, msg| {
+      b.iter(|| decode(black_box(msg), Expect::ScanListings, msg.len()))
+    });
+    group.bench_with_input(
+      BenchmarkId::new("decoder_chunked", listings),
+      &msg,
+      |b, msg| b.iter(|| decode(black_box(msg), Expect::ScanListings, CHUNK)),
+    );
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_retr, bench_list);
+criterion_main!(benches);
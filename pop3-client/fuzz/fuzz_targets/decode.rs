@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pop3_client_fuzz::EXPECTS;
+
+fuzz_target!(|data: &[u8]| {
+  // the first byte picks the kind of the first response, and each following one takes the next
+  // kind, as a session of different commands would
+  let (&kind, data) = match data.split_first() {
+    Some(split) => split,
+    None => return,
+  };
+  let mut kind = kind as usize;
+  pop3_client_fuzz::decode(data, || {
+    kind += 1;
+    EXPECTS[(kind - 1) % EXPECTS.len()]
+  });
+});
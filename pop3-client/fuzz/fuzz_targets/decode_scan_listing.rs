@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pop3_client::parser::Expect;
+
+fuzz_target!(|data: &[u8]| {
+  pop3_client_fuzz::decode(data, || Expect::ScanListing);
+});
@@ -0,0 +1,49 @@
+//! the decoding loop shared by the fuzz targets
+
+use bytes::BytesMut;
+use pop3_client::{
+  parser::{Expect, ResponseDecoder},
+  ParseMode,
+};
+use tokio_util::codec::Decoder;
+
+/// every kind of response, for targets choosing it from their input
+pub const EXPECTS: [Expect; 7] = [
+  Expect::Status,
+  Expect::Stat,
+  Expect::ScanListing,
+  Expect::ScanListings,
+  Expect::UniqueIdListing,
+  Expect::UniqueIdListings,
+  Expect::Mail,
+];
+
+/// feed the responses in `data` to a decoder, in reads of a length and with a parse mode taken
+/// from the first byte, expecting the kind `next_expect` returns before each response
+pub fn decode(data: &[u8], mut next_expect: impl FnMut() -> Expect) {
+  let (&first, data) = match data.split_first() {
+    Some(split) => split,
+    None => return,
+  };
+  let mode = if first & 0x80 == 0 {
+    ParseMode::Strict
+  } else {
+    ParseMode::Lenient
+  };
+  let chunk_len = (first & 0x7f) as usize + 1;
+
+  let mut decoder = ResponseDecoder::new(mode);
+  decoder.expect(next_expect());
+  let mut buf = BytesMut::new();
+  for chunk in data.chunks(chunk_len) {
+    buf.extend_from_slice(chunk);
+    loop {
+      match decoder.decode(&mut buf) {
+        Ok(Some(_)) => decoder.expect(next_expect()),
+        Ok(None) => break,
+        // a malformed response ends the session
+        Err(_) => return,
+      }
+    }
+  }
+}
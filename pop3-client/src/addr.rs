@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Result};
+use std::{fmt, net::Ipv6Addr, str::FromStr};
+
+use crate::Security;
+
+/// `s` with `%XX` escapes decoded, as in URLs
+pub(crate) fn percent_decode(s: &str) -> Result<String> {
+  let mut decoded = Vec::with_capacity(s.len());
+  let mut bytes = s.bytes();
+  while let Some(byte) = bytes.next() {
+    if byte != b'%' {
+      decoded.push(byte);
+      continue;
+    }
+    let hex = [bytes.next(), bytes.next()];
+    let digit = |digit: Option<u8>| (digit? as char).to_digit(16);
+    match hex.map(digit) {
+      [Some(high), Some(low)] => decoded.push((high << 4 | low) as u8),
+      _ => return Err(anyhow! {"invalid percent-encoding in {}", s}),
+    }
+  }
+  Ok(String::from_utf8(decoded)?)
+}
+
+/// address of a POP3 server, as given by the user
+///
+/// accepted forms are a host name or IPv4 address with an optional `:port`, an IPv6 address,
+/// bracketed if followed by a port as in `[2001:db8::1]:995`, and `pop3://` and `pop3s://` URLs
+/// (RFC 2384), which may also carry a user name, as in `pop3s://alice@pop.example.com`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAddress {
+  /// host name or IP address, without brackets
+  pub host: String,
+  /// port, if given
+  pub port: Option<u16>,
+  /// security implied by the scheme of a URL, [`Security::Plain`] for `pop3://` and
+  /// [`Security::Tls`] for `pop3s://`
+  pub security: Option<Security>,
+  /// user name of a URL
+  pub username: Option<String>,
+}
+
+impl ServerAddress {
+  /// the security implied by a URL, or `security`
+  ///
+  /// as `pop3://` only rules out implicit TLS, it keeps [`Security::StartTls`]
+  pub fn security_or(&self, security: Security) -> Security {
+    match (self.security, security) {
+      (Some(Security::Plain), Security::StartTls) => Security::StartTls,
+      (Some(implied), _) => implied,
+      (None, security) => security,
+    }
+  }
+
+  /// the port, defaulting to 995 with [`Security::Tls`] and 110 otherwise
+  pub fn port_or_default(&self, security: Security) -> u16 {
+    self.port.unwrap_or(match security {
+      Security::Tls => 995,
+      Security::Plain | Security::StartTls => 110,
+    })
+  }
+}
+
+fn parse_port(port: &str) -> Result<u16> {
+  match port.parse() {
+    Ok(0) | Err(_) => Err(anyhow! {"invalid port {}", port}),
+    Ok(port) => Ok(port),
+  }
+}
+
+impl FromStr for ServerAddress {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let s = s.trim();
+    let (security, rest) = match s.split_once("://") {
+      Some((scheme, rest)) => match scheme.to_ascii_lowercase().as_str() {
+        "pop3" | "pop" => (Some(Security::Plain), rest),
+        "pop3s" | "pops" => (Some(Security::Tls), rest),
+        _ => {
+          return Err(anyhow! {"unsupported scheme {}://, expected pop3:// or pop3s://", scheme})
+        }
+      },
+      None => (None, s),
+    };
+    let (username, rest) = match security {
+      Some(_) => {
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+        match rest.rsplit_once('@') {
+          Some((userinfo, rest)) => {
+            // a `;AUTH=` mechanism may follow the user name, which is left to the caller to pick
+            let user = userinfo.split(';').next().unwrap_or_default();
+            let user = percent_decode(user)?;
+            (Some(user).filter(|user| !user.is_empty()), rest)
+          }
+          None => (None, rest),
+        }
+      }
+      None => (None, rest),
+    };
+
+    let (host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+      let (host, rest) = bracketed
+        .split_once(']')
+        .ok_or_else(|| anyhow! {"unterminated [ in address {}", s})?;
+      host
+        .parse::<Ipv6Addr>()
+        .map_err(|_| anyhow! {"invalid IPv6 address {}", host})?;
+      let port = match rest {
+        "" => None,
+        rest => match rest.strip_prefix(':') {
+          Some(port) => Some(parse_port(port)?),
+          None => return Err(anyhow! {"unexpected {} after IPv6 address", rest}),
+        },
+      };
+      (host, port)
+    } else if rest.parse::<Ipv6Addr>().is_ok() {
+      // a bare IPv6 address, where a port could not be told apart from the last group
+      (rest, None)
+    } else {
+      match rest.split_once(':') {
+        Some((host, port)) => (host, Some(parse_port(port)?)),
+        None => (rest, None),
+      }
+    };
+    if host.is_empty() {
+      return Err(anyhow! {"no host in address {}", s});
+    }
+    if let Some(c) = host
+      .chars()
+      .find(|c| c.is_whitespace() || c.is_control() || "/?#@[]%".contains(*c))
+    {
+      return Err(anyhow! {"invalid character {:?} in host {}", c, host});
+    }
+    Ok(Self {
+      host: host.to_string(),
+      port,
+      security,
+      username,
+    })
+  }
+}
+
+impl fmt::Display for ServerAddress {
+  /// `host[:port]`, with IPv6 addresses bracketed
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.host.contains(':') {
+      write!(f, "[{}]", self.host)?;
+    } else {
+      write!(f, "{}", self.host)?;
+    }
+    if let Some(port) = self.port {
+      write!(f, ":{}", port)?;
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn test_server_address() {
+  let parse = |s: &str| s.parse::<ServerAddress>().unwrap();
+  let addr = |host: &str, port: Option<u16>| ServerAddress {
+    host: host.to_string(),
+    port,
+    security: None,
+    username: None,
+  };
+
+  assert_eq!(parse("pop.example.com"), addr("pop.example.com", None));
+  assert_eq!(
+    parse("pop.example.com:995"),
+    addr("pop.example.com", Some(995))
+  );
+  assert_eq!(parse("192.0.2.1:110"), addr("192.0.2.1", Some(110)));
+  assert_eq!(parse("::1"), addr("::1", None));
+  assert_eq!(parse("2001:db8::1"), addr("2001:db8::1", None));
+  assert_eq!(parse("[2001:db8::1]"), addr("2001:db8::1", None));
+  assert_eq!(parse("[2001:db8::1]:995"), addr("2001:db8::1", Some(995)));
+
+  assert_eq!(
+    parse("pop3s://alice%40example.com;AUTH=*@[::1]/"),
+    ServerAddress {
+      host: "::1".to_string(),
+      port: None,
+      security: Some(Security::Tls),
+      username: Some("alice@example.com".to_string()),
+    }
+  );
+  let url = parse("POP3://pop.example.com:1110");
+  assert_eq!(url.security, Some(Security::Plain));
+  assert_eq!(url.security_or(Security::Tls), Security::Plain);
+  assert_eq!(url.security_or(Security::StartTls), Security::StartTls);
+  assert_eq!(
+    parse("pop3s://pop.example.com").security_or(Security::StartTls),
+    Security::Tls
+  );
+  assert_eq!(url.port_or_default(Security::Tls), 1110);
+  assert_eq!(parse("pop.example.com").port_or_default(Security::Tls), 995);
+  assert_eq!(parse("::1").port_or_default(Security::StartTls), 110);
+
+  assert_eq!(parse("[::1]:995").to_string(), "[::1]:995");
+  assert_eq!(
+    parse("pop3://pop.example.com").to_string(),
+    "pop.example.com"
+  );
+
+  for invalid in [
+    "",
+    ":995",
+    "pop.example.com:",
+    "pop.example.com:pop3",
+    "pop.example.com:0",
+    "pop.example.com:65536",
+    "[::1",
+    "[::1]995",
+    "[pop.example.com]:995",
+    "1:2:3",
+    "imap://pop.example.com",
+    "pop example.com",
+  ] {
+    assert!(invalid.parse::<ServerAddress>().is_err(), "{}", invalid);
+  }
+}
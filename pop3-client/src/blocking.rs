@@ -0,0 +1,114 @@
+//! a blocking POP3 client, for programs without an async runtime
+//!
+//! [`Pop3`] mirrors [`crate::Pop3`] over [`std::net::TcpStream`], running the same session with
+//! blocking I/O
+
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use std::{
+  io::{self, ErrorKind, Read, Write},
+  net::SocketAddr,
+};
+
+use crate::{
+  dial, proxy,
+  session::{block_on, client_methods, Session, Transport},
+  tls::{self, Verify},
+  tunnel, ConnectOptions, ConnectionInfo, ParseMode, TlsConfig, TlsSession,
+};
+
+pub(crate) trait ReadWrite: Read + Write {}
+impl<T> ReadWrite for T where T: Read + Write {}
+
+/// the connection under a session, possibly wrapped in TLS
+pub(crate) type Stream = Box<dyn ReadWrite + Send>;
+
+/// the transport of a session, a type of its own for the futures of the session to be `Send`,
+/// which the lifetime of a trait object would defeat
+pub(crate) struct Connection(Stream);
+
+// every call completes before returning, as `block_on` expects
+impl Transport for Connection {
+  async fn open(
+    domain: &str,
+    port: u16,
+    options: &ConnectOptions,
+  ) -> Result<(Self, Option<SocketAddr>)> {
+    if let Some(tunnel) = &options.tunnel {
+      let tunnel = tunnel::spawn_blocking(tunnel, domain, port)?;
+      return Ok((Self(Box::new(tunnel)), None));
+    }
+    let tcp = match &options.proxy {
+      Some(proxy) => proxy::connect_blocking(proxy, domain, port)?,
+      None => dial::connect_blocking(domain, port)?,
+    };
+    let remote_addr = tcp.peer_addr()?;
+    Ok((Self(Box::new(tcp)), Some(remote_addr)))
+  }
+
+  async fn start_tls(
+    self,
+    domain: &str,
+    config: &TlsConfig,
+    verify: Verify,
+  ) -> Result<(Self, TlsSession)> {
+    let (stream, session) = tls::connect_blocking(domain, self.0, config, verify)?;
+    Ok((Self(stream), session))
+  }
+
+  async fn read_buf(&mut self, buf: &mut BytesMut) -> io::Result<usize> {
+    let len = buf.len();
+    buf.resize(buf.capacity(), 0);
+    loop {
+      match self.0.read(&mut buf[len..]) {
+        Err(err) if err.kind() == ErrorKind::Interrupted => {}
+        read => {
+          buf.truncate(len + read.as_ref().map_or(0, |read| *read));
+          return read;
+        }
+      }
+    }
+  }
+
+  async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+    self.0.write_all(buf)?;
+    self.0.flush()
+  }
+}
+
+/// a blocking connection to a POP3 server
+///
+/// commands are sent one at a time, and each method returns once the whole response is received
+pub struct Pop3 {
+  session: Session<Connection>,
+}
+
+impl Pop3 {
+  /// connect to `addr`, given as a [`ServerAddress`](crate::ServerAddress), and read the greeting
+  ///
+  /// the port defaults to 995 with TLS and 110 without
+  pub fn new(addr: &str, with_tls: bool) -> Result<(Self, String)> {
+    Self::with_mode(addr, with_tls, ParseMode::default())
+  }
+
+  /// like [`Pop3::new`], checking responses according to `mode`
+  pub fn with_mode(addr: &str, with_tls: bool, mode: ParseMode) -> Result<(Self, String)> {
+    Self::connect(addr, &ConnectOptions::with_tls(with_tls, mode))
+  }
+
+  /// like [`crate::Pop3::connect`], blocking
+  pub fn connect(addr: &str, options: &ConnectOptions) -> Result<(Self, String)> {
+    let (session, greeting) = block_on(Session::connect(addr, options))?;
+    Ok((Self { session }, greeting))
+  }
+
+  client_methods!(blocking);
+
+  /// end the session, returning the farewell text
+  pub fn quit(self) -> Result<String> {
+    block_on(self.session.quit())
+  }
+}
+
+#[cfg(test)]
+crate::test_support::client_tests!(#[test]);
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use std::{io, net::SocketAddr, pin::Pin};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+  dial, proxy,
+  session::{client_methods, Session, Transport},
+  tls::{self, Verify},
+  tunnel, ConnectOptions, ConnectionInfo, ParseMode, TlsConfig, TlsSession,
+};
+
+pub(crate) trait AsyncReadWrite: AsyncRead + AsyncWrite {}
+impl<T> AsyncReadWrite for T where T: AsyncRead + AsyncWrite {}
+
+/// the connection under a session, possibly wrapped in TLS
+pub(crate) type Stream = Pin<Box<dyn AsyncReadWrite + Send>>;
+
+/// the transport of a session, a type of its own for the futures of the session to be `Send`,
+/// which the lifetime of a trait object would defeat
+pub(crate) struct Connection(Stream);
+
+impl Transport for Connection {
+  async fn open(
+    domain: &str,
+    port: u16,
+    options: &ConnectOptions,
+  ) -> Result<(Self, Option<SocketAddr>)> {
+    if let Some(tunnel) = &options.tunnel {
+      return Ok((Self(Box::pin(tunnel::spawn(tunnel, domain, port)?)), None));
+    }
+    let tcp = match &options.proxy {
+      Some(proxy) => proxy::connect(proxy, domain, port).await?,
+      None => dial::connect(domain, port).await?,
+    };
+    let remote_addr = tcp.peer_addr()?;
+    Ok((Self(Box::pin(tcp)), Some(remote_addr)))
+  }
+
+  async fn start_tls(
+    self,
+    domain: &str,
+    config: &TlsConfig,
+    verify: Verify,
+  ) -> Result<(Self, TlsSession)> {
+    let (stream, session) = tls::connect(domain, self.0, config, verify).await?;
+    Ok((Self(stream), session))
+  }
+
+  async fn read_buf(&mut self, buf: &mut BytesMut) -> io::Result<usize> {
+    self.0.read_buf(buf).await
+  }
+
+  async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+    self.0.write_all(buf).await?;
+    self.0.flush().await
+  }
+}
+
+/// a connection to a POP3 server
+///
+/// commands are sent one at a time, and each method returns once the whole response is received
+pub struct Pop3 {
+  session: Session<Connection>,
+}
+
+impl Pop3 {
+  /// connect to `addr`, given as a [`ServerAddress`](crate::ServerAddress), and read the greeting
+  ///
+  /// the port defaults to 995 with TLS and 110 without
+  pub async fn new(addr: &str, with_tls: bool) -> Result<(Self, String)> {
+    Self::with_mode(addr, with_tls, ParseMode::default()).await
+  }
+
+  /// like [`Pop3::new`], checking responses according to `mode`
+  pub async fn with_mode(addr: &str, with_tls: bool, mode: ParseMode) -> Result<(Self, String)> {
+    Self::connect(addr, &ConnectOptions::with_tls(with_tls, mode)).await
+  }
+
+  /// connect to `addr`, given as a [`ServerAddress`](crate::ServerAddress), as set by `options`
+  /// and read the greeting
+  ///
+  /// the scheme of a URL overrides `options.security`, see
+  /// [`ServerAddress::security_or`](crate::ServerAddress::security_or). With
+  /// [`Security::StartTls`](crate::Security::StartTls) the session is upgraded before returning, and the port defaults to 995
+  /// with [`Security::Tls`](crate::Security::Tls) and 110 otherwise. A server certificate that is not trusted is
+  /// reported as [`UntrustedCertificate`](crate::UntrustedCertificate).
+  pub async fn connect(addr: &str, options: &ConnectOptions) -> Result<(Self, String)> {
+    let (session, greeting) = Session::connect(addr, options).await?;
+    Ok((Self { session }, greeting))
+  }
+
+  client_methods!(async);
+
+  /// end the session, returning the farewell text
+  pub async fn quit(self) -> Result<String> {
+    self.session.quit().await
+  }
+}
+
+#[test]
+fn test_pop3_send() {
+  // the futures of the client can be spawned on a multithreaded runtime
+  fn is_send<T: Send>(_: &T) {}
+  is_send(&Pop3::connect("localhost", &ConnectOptions::default()));
+  let _ = |pop3: &mut Pop3| is_send(&pop3.retr(1));
+  let _ = |pop3: Pop3| is_send(&pop3.quit());
+}
+
+#[cfg(test)]
+crate::test_support::client_tests!(#[tokio::test] async);
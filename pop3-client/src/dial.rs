@@ -0,0 +1,224 @@
+//! connection establishment racing the addresses of a host, in the manner of Happy Eyeballs
+//! (RFC 8305), so that an unreachable IPv6 route does not hold up a working IPv4 one
+//!
+//! addresses are tried in the order the resolver returned them with the families interleaved,
+//! a new attempt starting whenever the previous one fails or has been pending for
+//! [`ATTEMPT_DELAY`], and the first connection established wins
+
+use anyhow::{anyhow, Result};
+use std::{
+  future::{poll_fn, Future},
+  io,
+  net::SocketAddr,
+  pin::Pin,
+  task::Poll,
+  time::Duration,
+};
+
+/// delay before starting the next attempt while the previous ones are pending, the value
+/// recommended by RFC 8305
+pub(crate) const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// `addrs` reordered to alternate between IPv6 and IPv4, starting with the family of the first
+/// address and otherwise keeping the order of the resolver
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+  let first_v6 = match addrs.first() {
+    Some(addr) => addr.is_ipv6(),
+    None => return addrs,
+  };
+  let len = addrs.len();
+  let (first, second): (Vec<_>, Vec<_>) = addrs
+    .into_iter()
+    .partition(|addr| addr.is_ipv6() == first_v6);
+  let mut interleaved = Vec::with_capacity(len);
+  let (mut first, mut second) = (first.into_iter(), second.into_iter());
+  loop {
+    match (first.next(), second.next()) {
+      (None, None) => return interleaved,
+      (a, b) => interleaved.extend(a.into_iter().chain(b)),
+    }
+  }
+}
+
+fn no_address(host: &str, port: u16) -> anyhow::Error {
+  anyhow! {"{}:{} did not resolve to any address", host, port}
+}
+
+/// connect to `host:port` over TCP, racing its addresses
+pub(crate) async fn connect(host: &str, port: u16) -> Result<tokio::net::TcpStream> {
+  let addrs = tokio::net::lookup_host((host, port)).await?.collect();
+  race(interleave(addrs))
+    .await?
+    .ok_or_else(|| no_address(host, port))
+}
+
+type Attempt = Pin<Box<dyn Future<Output = io::Result<tokio::net::TcpStream>> + Send>>;
+
+enum Event {
+  Connected(tokio::net::TcpStream),
+  Failed(usize, io::Error),
+  Delay,
+}
+
+/// the first connection established to any of `addrs`, tried in order, or `None` without any
+/// address
+async fn race(addrs: Vec<SocketAddr>) -> io::Result<Option<tokio::net::TcpStream>> {
+  let mut addrs = addrs.into_iter().peekable();
+  let mut attempts: Vec<Attempt> = vec![];
+  let mut last_err = None;
+  let delay = tokio::time::sleep(ATTEMPT_DELAY);
+  tokio::pin!(delay);
+  // an attempt has failed or the delay has passed every time around
+  loop {
+    match addrs.next() {
+      Some(addr) => {
+        attempts.push(Box::pin(tokio::net::TcpStream::connect(addr)));
+        delay
+          .as_mut()
+          .reset(tokio::time::Instant::now() + ATTEMPT_DELAY);
+      }
+      None if attempts.is_empty() => return last_err.map_or(Ok(None), Err),
+      None => {}
+    }
+    let more = addrs.peek().is_some();
+    let event = poll_fn(|cx| {
+      for (i, attempt) in attempts.iter_mut().enumerate() {
+        if let Poll::Ready(result) = attempt.as_mut().poll(cx) {
+          return Poll::Ready(match result {
+            Ok(stream) => Event::Connected(stream),
+            Err(err) => Event::Failed(i, err),
+          });
+        }
+      }
+      match more {
+        true => delay.as_mut().poll(cx).map(|()| Event::Delay),
+        false => Poll::Pending,
+      }
+    })
+    .await;
+    match event {
+      // the attempts still pending are dropped, closing their sockets
+      Event::Connected(stream) => return Ok(Some(stream)),
+      // the next attempt starts right away, not to wait out the delay for one already failed
+      Event::Failed(i, err) => {
+        drop(attempts.remove(i));
+        last_err = Some(err);
+      }
+      Event::Delay => {}
+    }
+  }
+}
+
+/// like [`connect`], blocking
+///
+/// each attempt runs on a thread of its own, which outlives the call until its connection is
+/// established or fails when another attempt wins
+#[cfg(feature = "blocking")]
+pub(crate) fn connect_blocking(host: &str, port: u16) -> Result<std::net::TcpStream> {
+  use std::net::ToSocketAddrs;
+
+  let addrs = (host, port).to_socket_addrs()?.collect();
+  race_blocking(interleave(addrs))?.ok_or_else(|| no_address(host, port))
+}
+
+/// like [`race`], blocking
+#[cfg(feature = "blocking")]
+fn race_blocking(addrs: Vec<SocketAddr>) -> io::Result<Option<std::net::TcpStream>> {
+  use std::sync::mpsc::{self, RecvTimeoutError};
+
+  let (tx, rx) = mpsc::channel();
+  let mut addrs = addrs.into_iter().peekable();
+  let mut pending = 0;
+  let mut last_err = None;
+  loop {
+    let timeout = match addrs.next() {
+      Some(addr) => {
+        let tx = tx.clone();
+        // the stream is dropped, closing it, when the race is over before it is received
+        std::thread::spawn(move || tx.send(std::net::TcpStream::connect(addr)));
+        pending += 1;
+        addrs.peek().map(|_| ATTEMPT_DELAY)
+      }
+      None if pending == 0 => return last_err.map_or(Ok(None), Err),
+      None => None,
+    };
+    let result = match timeout {
+      Some(timeout) => match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => continue,
+        Err(RecvTimeoutError::Disconnected) => unreachable!("a sender is kept"),
+      },
+      None => rx.recv().expect("a sender is kept"),
+    };
+    pending -= 1;
+    match result {
+      Ok(stream) => return Ok(Some(stream)),
+      Err(err) => last_err = Some(err),
+    }
+  }
+}
+
+#[test]
+fn test_interleave() {
+  let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+  let (a6, b6, c6) = (
+    addr("[2001:db8::1]:110"),
+    addr("[2001:db8::2]:110"),
+    addr("[2001:db8::3]:110"),
+  );
+  let (a4, b4) = (addr("192.0.2.1:110"), addr("192.0.2.2:110"));
+
+  assert_eq!(interleave(vec![]), vec![]);
+  assert_eq!(
+    interleave(vec![a6, b6, c6, a4, b4]),
+    vec![a6, a4, b6, b4, c6]
+  );
+  assert_eq!(interleave(vec![a4, b4, a6]), vec![a4, a6, b4]);
+  assert_eq!(interleave(vec![a6, b6]), vec![a6, b6]);
+}
+
+/// an address nothing listens on, and one which accepts connections
+#[cfg(test)]
+fn refused_and_listening() -> (SocketAddr, std::net::TcpListener) {
+  let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+  let refused = closed.local_addr().unwrap();
+  drop(closed);
+  (refused, std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_race() {
+  let (refused, listener) = refused_and_listening();
+  let listening = listener.local_addr().unwrap();
+  // TEST-NET-1 is not routed, so the attempt either hangs or fails right away
+  let unroutable = "192.0.2.1:110".parse().unwrap();
+
+  let stream = race(vec![refused, listening]).await.unwrap().unwrap();
+  assert_eq!(stream.peer_addr().unwrap(), listening);
+  let start = std::time::Instant::now();
+  let stream = race(vec![unroutable, listening]).await.unwrap().unwrap();
+  assert_eq!(stream.peer_addr().unwrap(), listening);
+  assert!(start.elapsed() < ATTEMPT_DELAY * 4);
+
+  assert!(race(vec![refused]).await.is_err());
+  assert!(race(vec![]).await.unwrap().is_none());
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn test_race_blocking() {
+  let (refused, listener) = refused_and_listening();
+  let listening = listener.local_addr().unwrap();
+  let unroutable = "192.0.2.1:110".parse().unwrap();
+
+  let stream = race_blocking(vec![refused, listening]).unwrap().unwrap();
+  assert_eq!(stream.peer_addr().unwrap(), listening);
+  let start = std::time::Instant::now();
+  let stream = race_blocking(vec![unroutable, listening]).unwrap().unwrap();
+  assert_eq!(stream.peer_addr().unwrap(), listening);
+  assert!(start.elapsed() < ATTEMPT_DELAY * 4);
+
+  assert!(race_blocking(vec![refused]).is_err());
+  assert!(race_blocking(vec![]).unwrap().is_none());
+}
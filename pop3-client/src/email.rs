@@ -0,0 +1,553 @@
+//! conversion of retrieved mail into displayable parts
+
+use anyhow::{anyhow, Error};
+use charset::Charset;
+use mailparse::{
+  addrparse_header, dateparse, parse_mail, DispositionType, MailAddr, MailHeader, MailHeaderMap,
+  ParsedMail, SingleInfo,
+};
+use serde::{Deserialize, Serialize};
+
+/// metadata of a non-text part, whose content is read with [`part_content`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+  /// index of the part in `Email::parts`
+  part: usize,
+  filename: Option<String>,
+  mimetype: String,
+  content_id: Option<String>,
+  disposition: String,
+  /// size of the decoded content in bytes
+  size: usize,
+}
+
+impl Attachment {
+  /// index of the part, to pass to [`part_content`]
+  pub fn part(&self) -> usize {
+    self.part
+  }
+
+  /// filename suggested by the sender, which may contain path separators
+  pub fn filename(&self) -> Option<&str> {
+    self.filename.as_deref()
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum PartType {
+  Html(String),
+  Text(String),
+  Bin(Attachment),
+}
+
+/// a leaf of the MIME tree
+#[derive(Serialize, Deserialize, Debug)]
+struct Part {
+  /// indices of the subparts leading from the root of the MIME tree to this part
+  path: Vec<usize>,
+  mimetype: String,
+  content: PartType,
+}
+
+/// a mailbox from an address header
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+  /// decoded display name
+  name: Option<String>,
+  email: String,
+  /// name of the group the address is listed in
+  group: Option<String>,
+}
+
+impl Address {
+  fn new(info: &SingleInfo, group: Option<&str>) -> Self {
+    Self {
+      name: info.display_name.clone(),
+      email: info.addr.clone(),
+      group: group.map(str::to_string),
+    }
+  }
+}
+
+/// addresses of all `key` headers, keeping unparsable values as a bare display name
+fn parse_addresses(headers: &[MailHeader], key: &str) -> Vec<Address> {
+  let mut addresses = vec![];
+  for header in headers.get_all_headers(key) {
+    match addrparse_header(header) {
+      Ok(list) => {
+        for addr in list.iter() {
+          match addr {
+            MailAddr::Single(info) => addresses.push(Address::new(info, None)),
+            MailAddr::Group(group) => addresses.extend(
+              group
+                .addrs
+                .iter()
+                .map(|info| Address::new(info, Some(&group.group_name))),
+            ),
+          }
+        }
+      }
+      Err(_) => addresses.push(Address {
+        name: Some(header.get_value()),
+        email: String::new(),
+        group: None,
+      }),
+    }
+  }
+  addresses
+}
+
+/// a point in time, keeping the timezone of the sender
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+  /// seconds since the unix epoch
+  timestamp: i64,
+  /// offset of the sender's timezone from UTC, in minutes
+  offset: i32,
+}
+
+impl Date {
+  /// the `Date:` of a mail, or the timestamp of the earliest `Received:` if it is missing or
+  /// malformed
+  pub fn of_headers(headers: &[MailHeader]) -> Option<Self> {
+    let date = headers.get_first_value("Date").unwrap_or_default();
+    parse_date(&date).or_else(|| {
+      // relays prepend their `Received:`, so the last one is the closest to the sender
+      let received = headers.get_all_values("Received").pop()?;
+      parse_date(&received[received.rfind(';')? + 1..])
+    })
+  }
+
+  /// seconds since the unix epoch
+  pub fn timestamp(&self) -> i64 {
+    self.timestamp
+  }
+}
+
+/// timezone offset in minutes at the end of an RFC 5322 date
+fn parse_offset(date: &str) -> Option<i32> {
+  // drop a trailing comment such as `(CST)`
+  let date = match date.trim_end().strip_suffix(')') {
+    Some(date) => &date[..date.rfind('(')?],
+    None => date,
+  };
+  let zone = date.split_whitespace().last()?;
+  let hours = match zone.to_ascii_uppercase().as_str() {
+    "UT" | "GMT" | "Z" => 0,
+    "EDT" => -4,
+    "EST" | "CDT" => -5,
+    "CST" | "MDT" => -6,
+    "MST" | "PDT" => -7,
+    "PST" => -8,
+    _ => {
+      let (sign, digits) = match zone.as_bytes().first()? {
+        b'+' => (1, &zone[1..]),
+        b'-' => (-1, &zone[1..]),
+        _ => return None,
+      };
+      if digits.len() != 4 || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+      }
+      let hours = digits[..2].parse::<i32>().ok()?;
+      let minutes = digits[2..].parse::<i32>().ok()?;
+      return Some(sign * (hours * 60 + minutes));
+    }
+  };
+  Some(hours * 60)
+}
+
+/// whether `date` has the day, month, year and time of an RFC 5322 date, which `dateparse` does
+/// not check, taking text such as `yesterday` for the epoch
+fn is_rfc5322_date(date: &str) -> bool {
+  const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+  ];
+  let digits = |s: &str, min: usize, max: usize| {
+    (min..=max).contains(&s.len()) && s.bytes().all(|c| c.is_ascii_digit())
+  };
+  // the day of the week is optional
+  let date = date.split_once(',').map_or(date, |(_, date)| date);
+  let mut tokens = date.split_whitespace();
+  match (tokens.next(), tokens.next(), tokens.next(), tokens.next()) {
+    (Some(day), Some(month), Some(year), Some(time)) => {
+      let fields: Vec<_> = time.split(':').collect();
+      digits(day, 1, 2)
+        && MONTHS.iter().any(|name| name.eq_ignore_ascii_case(month))
+        && digits(year, 2, 4)
+        && (2..=3).contains(&fields.len())
+        && fields.iter().all(|field| digits(field, 2, 2))
+    }
+    _ => false,
+  }
+}
+
+fn parse_date(date: &str) -> Option<Date> {
+  if !is_rfc5322_date(date) {
+    return None;
+  }
+  Some(Date {
+    timestamp: dateparse(date).ok()?,
+    offset: parse_offset(date).unwrap_or(0),
+  })
+}
+
+/// a mail converted for display, built with `Email::try_from(raw_mail)`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Email {
+  message_id: Option<String>,
+  subject: String,
+  from: Vec<Address>,
+  sender: Vec<Address>,
+  reply_to: Vec<Address>,
+  to: Vec<Address>,
+  cc: Vec<Address>,
+  bcc: Vec<Address>,
+  time: String,
+  /// parsed `Date:`, or the timestamp of the earliest `Received:` if it is missing or malformed
+  date: Option<Date>,
+  parts: Vec<Part>,
+  /// index into `parts` of the part best suited to display as the message body
+  body: Option<usize>,
+}
+
+fn is_attachment(mail: &ParsedMail) -> bool {
+  mail.get_content_disposition().disposition == DispositionType::Attachment
+}
+
+/// collect the leaves of the MIME tree with their paths, depth first
+fn collect_leaves<'a, 'b>(
+  mail: &'b ParsedMail<'a>,
+  path: &mut Vec<usize>,
+  leaves: &mut Vec<(Vec<usize>, &'b ParsedMail<'a>)>,
+) {
+  if mail.subparts.is_empty() {
+    leaves.push((path.clone(), mail));
+  } else {
+    for (idx, part) in mail.subparts.iter().enumerate() {
+      path.push(idx);
+      collect_leaves(part, path, leaves);
+      path.pop();
+    }
+  }
+}
+
+fn leaves<'a, 'b>(mail: &'b ParsedMail<'a>) -> Vec<(Vec<usize>, &'b ParsedMail<'a>)> {
+  let mut leaves = vec![];
+  collect_leaves(mail, &mut vec![], &mut leaves);
+  leaves
+}
+
+fn attachment(part: usize, mail: &ParsedMail) -> Result<Attachment, Error> {
+  let disposition = mail.get_content_disposition();
+  Ok(Attachment {
+    part,
+    filename: disposition
+      .params
+      .get("filename")
+      .or_else(|| mail.ctype.params.get("name"))
+      .cloned(),
+    mimetype: mail.ctype.mimetype.to_lowercase(),
+    content_id: mail.headers.get_first_value("Content-ID").map(|id| {
+      id.trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+    }),
+    disposition: match disposition.disposition {
+      DispositionType::Inline => "inline".to_string(),
+      DispositionType::Attachment => "attachment".to_string(),
+      DispositionType::FormData => "form-data".to_string(),
+      DispositionType::Extension(extension) => extension,
+    },
+    size: mail.get_body_raw()?.len(),
+  })
+}
+
+/// the raw mail as text for display, decoded with the charset declared in the mail when it is not
+/// valid UTF-8
+pub fn decode_source(bytes: &[u8]) -> String {
+  if let Ok(source) = std::str::from_utf8(bytes) {
+    return source.to_string();
+  }
+  let charset = parse_mail(bytes).ok().and_then(|mail| {
+    leaves(&mail)
+      .into_iter()
+      .map(|(_, leaf)| leaf.ctype.charset.to_lowercase())
+      .find(|charset| !matches!(charset.as_str(), "us-ascii" | "utf-8" | "utf8"))
+      .and_then(|charset| Charset::for_label(charset.as_bytes()))
+  });
+  match charset {
+    Some(charset) => charset.decode_without_bom_handling(bytes).0.into_owned(),
+    // every byte is a valid ISO-8859-1 character
+    None => bytes.iter().map(|c| *c as char).collect(),
+  }
+}
+
+/// decoded content of the part at `part` in `Email::parts` of the raw mail
+pub fn part_content(bytes: &[u8], part: usize) -> Result<Vec<u8>, Error> {
+  let mail = parse_mail(bytes)?;
+  let leaves = leaves(&mail);
+  let (_, leaf) = leaves.get(part).ok_or_else(|| anyhow! {"no such part"})?;
+  Ok(leaf.get_body_raw()?)
+}
+
+/// path of the part best suited to display as the message body
+fn find_body(mail: &ParsedMail, path: &mut Vec<usize>) -> Option<Vec<usize>> {
+  if is_attachment(mail) {
+    return None;
+  }
+  let mimetype = mail.ctype.mimetype.to_lowercase();
+  if mail.subparts.is_empty() {
+    return match mimetype.as_str() {
+      "text/plain" | "text/html" => Some(path.clone()),
+      _ => None,
+    };
+  }
+  let mut find_in = |idx: usize, part: &ParsedMail| {
+    path.push(idx);
+    let body = find_body(part, path);
+    path.pop();
+    body
+  };
+  if mimetype == "multipart/alternative" {
+    // alternatives are ordered by increasing faithfulness to the original (RFC 2046)
+    mail
+      .subparts
+      .iter()
+      .enumerate()
+      .rev()
+      .find_map(|(idx, part)| find_in(idx, part))
+  } else {
+    mail
+      .subparts
+      .iter()
+      .enumerate()
+      .find_map(|(idx, part)| find_in(idx, part))
+  }
+}
+
+impl TryFrom<&[u8]> for Email {
+  type Error = Error;
+
+  fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+    let mut email = Self::default();
+    let mail = parse_mail(bytes)?;
+    email.message_id = mail
+      .headers
+      .get_first_value("Message-ID")
+      .map(|id| id.trim().to_string())
+      .filter(|id| !id.is_empty());
+    if let Some(subject) = mail.headers.get_first_value("Subject") {
+      email.subject = subject;
+    }
+    email.from = parse_addresses(&mail.headers, "From");
+    email.sender = parse_addresses(&mail.headers, "Sender");
+    email.reply_to = parse_addresses(&mail.headers, "Reply-To");
+    email.to = parse_addresses(&mail.headers, "To");
+    email.cc = parse_addresses(&mail.headers, "Cc");
+    email.bcc = parse_addresses(&mail.headers, "Bcc");
+    if let Some(time) = mail.headers.get_first_value("Date") {
+      email.time = time;
+    }
+    email.date = Date::of_headers(&mail.headers);
+    for (idx, (path, leaf)) in leaves(&mail).into_iter().enumerate() {
+      let mimetype = leaf.ctype.mimetype.to_lowercase();
+      let content = match mimetype.as_str() {
+        "text/plain" if !is_attachment(leaf) => PartType::Text(leaf.get_body()?),
+        "text/html" if !is_attachment(leaf) => PartType::Html(leaf.get_body()?),
+        _ => PartType::Bin(attachment(idx, leaf)?),
+      };
+      email.parts.push(Part {
+        path,
+        mimetype,
+        content,
+      });
+    }
+    email.body = find_body(&mail, &mut vec![])
+      .and_then(|body| email.parts.iter().position(|part| part.path == body));
+
+    Ok(email)
+  }
+}
+
+impl Email {
+  /// `Message-ID:` including its angle brackets
+  pub fn message_id(&self) -> Option<&str> {
+    self.message_id.as_deref()
+  }
+
+  /// metadata of the parts that are not displayed as text
+  pub fn attachments(&self) -> Vec<Attachment> {
+    self
+      .parts
+      .iter()
+      .filter_map(|part| match &part.content {
+        PartType::Bin(attachment) => Some(attachment.clone()),
+        _ => None,
+      })
+      .collect()
+  }
+}
+
+#[test]
+fn test_single_part() {
+  let email =
+    Email::try_from(&b"Subject: hi\r\nContent-Type: text/plain\r\n\r\nhello\r\n"[..]).unwrap();
+  assert_eq!(email.parts.len(), 1);
+  assert!(email.parts[0].path.is_empty());
+  assert!(matches!(&email.parts[0].content, PartType::Text(text) if text.trim_end() == "hello"));
+  assert_eq!(email.body, Some(0));
+  assert_eq!(email.message_id(), None);
+}
+
+#[test]
+fn test_addresses() {
+  let email = Email::try_from(
+    &b"From: =?UTF-8?B?5byg5LiJ?= <zhang@example.com>\r\n\
+      To: a@example.com, \"B, Jr.\" <b@example.com>\r\n\
+      To: team: c@example.com, D <d@example.com>;\r\n\
+      Cc: not an address <\r\n\
+      Reply-To: <reply@example.com>\r\n\
+      \r\n\
+      body\r\n"[..],
+  )
+  .unwrap();
+
+  let address = |name: Option<&str>, email: &str, group: Option<&str>| Address {
+    name: name.map(str::to_string),
+    email: email.to_string(),
+    group: group.map(str::to_string),
+  };
+  assert_eq!(
+    email.from,
+    vec![address(Some("张三"), "zhang@example.com", None)]
+  );
+  assert_eq!(
+    email.to,
+    vec![
+      address(None, "a@example.com", None),
+      address(Some("B, Jr."), "b@example.com", None),
+      address(None, "c@example.com", Some("team")),
+      address(Some("D"), "d@example.com", Some("team")),
+    ]
+  );
+  assert_eq!(
+    email.reply_to,
+    vec![address(None, "reply@example.com", None)]
+  );
+  assert_eq!(email.cc.len(), 1);
+  assert!(email.sender.is_empty());
+  assert!(email.bcc.is_empty());
+}
+
+#[test]
+fn test_parse_offset() {
+  assert_eq!(parse_offset("Tue, 1 Jul 2003 10:52:37 +0200"), Some(120));
+  assert_eq!(parse_offset("Tue, 1 Jul 2003 10:52:37 -0330"), Some(-210));
+  assert_eq!(
+    parse_offset("Tue, 1 Jul 2003 10:52:37 +0800 (CST)"),
+    Some(480)
+  );
+  assert_eq!(parse_offset("Tue, 1 Jul 2003 10:52:37 GMT"), Some(0));
+  assert_eq!(parse_offset("Tue, 1 Jul 2003 10:52:37 PDT"), Some(-420));
+  assert_eq!(parse_offset("Tue, 1 Jul 2003 10:52:37"), None);
+  assert_eq!(parse_offset("Tue, 1 Jul 2003 10:52:37 +08"), None);
+  assert_eq!(parse_offset(""), None);
+}
+
+#[test]
+fn test_date() {
+  let email =
+    Email::try_from(&b"Date: Thu, 1 Jan 1970 08:00:01 +0800\r\n\r\nbody\r\n"[..]).unwrap();
+  assert_eq!(
+    email.date,
+    Some(Date {
+      timestamp: 1,
+      offset: 480
+    })
+  );
+
+  let email = Email::try_from(
+    &b"Received: from b by c; Fri, 2 Jan 1970 00:00:00 +0000\r\n\
+      Received: from a by b; Thu, 1 Jan 1970 00:01:00 -0100\r\n\
+      Date: yesterday\r\n\
+      \r\n\
+      body\r\n"[..],
+  )
+  .unwrap();
+  assert_eq!(email.time, "yesterday");
+  assert_eq!(
+    email.date,
+    Some(Date {
+      timestamp: 3660,
+      offset: -60
+    })
+  );
+
+  let email = Email::try_from(&b"Subject: no date\r\n\r\nbody\r\n"[..]).unwrap();
+  assert_eq!(email.date, None);
+
+  // free text is not taken for the epoch
+  assert_eq!(parse_date("yesterday"), None);
+  assert_eq!(parse_date("1 Jan"), None);
+  assert_eq!(
+    parse_date("Thu, 1 Jan 1970 00:00 +0000").map(|date| date.timestamp()),
+    Some(0)
+  );
+  assert_eq!(
+    parse_date("1 jan 70 00:00:00 GMT").map(|date| date.timestamp()),
+    Some(0)
+  );
+}
+
+#[test]
+fn test_decode_source() {
+  assert_eq!(
+    decode_source("Subject: 你好\r\n\r\n".as_bytes()),
+    "Subject: 你好\r\n\r\n"
+  );
+  assert_eq!(
+    decode_source(b"Content-Type: text/plain; charset=gbk\r\n\r\n\xc4\xe3\xba\xc3\r\n"),
+    "Content-Type: text/plain; charset=gbk\r\n\r\n你好\r\n"
+  );
+  assert_eq!(decode_source(b"\r\n\r\ncaf\xe9\r\n"), "\r\n\r\ncafé\r\n");
+}
+
+#[test]
+fn test_nested_alternative() {
+  let raw = b"Content-Type: multipart/mixed; boundary=outer\r\n\
+      \r\n\
+      --outer\r\n\
+      Content-Type: multipart/alternative; boundary=inner\r\n\
+      \r\n\
+      --inner\r\n\
+      Content-Type: text/plain\r\n\
+      \r\n\
+      plain\r\n\
+      --inner\r\n\
+      Content-Type: text/html\r\n\
+      \r\n\
+      <p>html</p>\r\n\
+      --inner--\r\n\
+      --outer\r\n\
+      Content-Type: application/pdf\r\n\
+      Content-Disposition: attachment; filename=a.pdf\r\n\
+      \r\n\
+      %PDF\r\n\
+      --outer--\r\n";
+  let email = Email::try_from(&raw[..]).unwrap();
+  let paths: Vec<_> = email.parts.iter().map(|part| part.path.clone()).collect();
+  assert_eq!(paths, vec![vec![0, 0], vec![0, 1], vec![1]]);
+  assert!(matches!(email.parts[0].content, PartType::Text(_)));
+  assert!(matches!(email.parts[1].content, PartType::Html(_)));
+  assert_eq!(email.body, Some(1));
+
+  let attachments = email.attachments();
+  assert_eq!(attachments.len(), 1);
+  assert_eq!(attachments[0].part, 2);
+  assert_eq!(attachments[0].filename(), Some("a.pdf"));
+  assert_eq!(attachments[0].mimetype, "application/pdf");
+  assert_eq!(attachments[0].disposition, "attachment");
+  assert!(part_content(&raw[..], 2).unwrap().starts_with(b"%PDF"));
+  assert!(part_content(&raw[..], 3).is_err());
+}
@@ -0,0 +1,127 @@
+//! an asynchronous POP3 client (RFC 1939)
+//!
+//! [`Pop3`] drives a session over TCP, TLS or TCP upgraded with `STLS`, built on [`Msg`] to format
+//! commands and on the incremental [`parser::ResponseDecoder`] to read responses. TLS is provided
+//! by `native-tls` by default, or by `rustls` with the feature of that name. The `blocking` feature adds
+//! [`blocking::Pop3`], the same client without an async runtime, and the `email` feature adds
+//! [`email::Email`], a conversion of retrieved mail into displayable parts.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use pop3_client::Pop3;
+//!
+//! let (mut pop3, _greeting) = Pop3::new("pop.example.com", true).await?;
+//! pop3.user("alice").await?;
+//! pop3.pass("secret").await?;
+//! let (list, _) = pop3.list(None).await?;
+//! for (id, _size) in list {
+//!   let (mail, _) = pop3.retr(id).await?;
+//!   println!("{}", String::from_utf8_lossy(&mail));
+//! }
+//! pop3.quit().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+mod addr;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod client;
+mod dial;
+#[cfg(feature = "email")]
+pub mod email;
+mod msg;
+pub mod parser;
+mod proxy;
+mod session;
+#[cfg(test)]
+mod test_support;
+mod tls;
+mod tunnel;
+
+pub use addr::ServerAddress;
+pub use client::Pop3;
+pub use msg::{Msg, MsgError};
+pub use proxy::{Proxy, ProxyCredentials, ProxyKind};
+pub use tls::{
+  Certificate, CertificateInfo, Fingerprint, Identity, TlsConfig, TlsSession, TlsVersion,
+  UntrustedCertificate,
+};
+pub use tunnel::Tunnel;
+
+use std::net::SocketAddr;
+
+/// how strictly server responses are checked against RFC 1939
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+  /// only accept well-formed responses
+  #[default]
+  Strict,
+  /// also accept common deviations of non-conforming servers, such as bare LF line endings,
+  /// lowercase status indicators and extra whitespace in scan listings
+  Lenient,
+}
+
+/// how the connection to the server is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Security {
+  /// plain text, exposing the password to the network
+  Plain,
+  /// TLS from the start, on port 995 by default
+  #[default]
+  Tls,
+  /// plain text upgraded to TLS with `STLS` (RFC 2595) right after the greeting, on port 110 by
+  /// default
+  StartTls,
+}
+
+/// how to connect to a server
+#[derive(Clone, Default)]
+pub struct ConnectOptions {
+  /// how the connection is secured
+  pub security: Security,
+  /// how strictly responses are checked
+  pub mode: ParseMode,
+  /// TLS settings, for both implicit TLS and `STLS`
+  pub tls: TlsConfig,
+  /// proxy to connect through, instead of connecting directly
+  pub proxy: Option<Proxy>,
+  /// command to speak to the server through, instead of connecting, which rules out a proxy
+  pub tunnel: Option<Tunnel>,
+}
+
+impl ConnectOptions {
+  /// options of `Pop3::new` and `Pop3::with_mode`, which only choose between TLS and plain text
+  pub(crate) fn with_tls(with_tls: bool, mode: ParseMode) -> Self {
+    Self {
+      security: if with_tls {
+        Security::Tls
+      } else {
+        Security::Plain
+      },
+      mode,
+      ..Default::default()
+    }
+  }
+}
+
+/// how a session was set up, as reported by [`Pop3::connection_info`]
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+  /// address the connection was made to, which is the proxy when there is one, and otherwise
+  /// whichever address of the server answered first; `None` through a [`Tunnel`]
+  pub remote_addr: Option<SocketAddr>,
+  /// how the connection is secured, [`Security::StartTls`] meaning `STLS` was used
+  pub security: Security,
+  /// text of the greeting of the server
+  pub greeting: String,
+  /// what was negotiated for TLS, unless the connection is [`Security::Plain`]
+  pub tls: Option<TlsSession>,
+  /// whether the session started logged in, through a [`Tunnel`] with `preauth`, so that there is
+  /// no `USER` and `PASS` to send
+  pub preauthenticated: bool,
+}
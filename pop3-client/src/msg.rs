@@ -0,0 +1,242 @@
+use anyhow::Result;
+use std::io::Write;
+
+/// maximum length of a command line, including the terminating CRLF (RFC 2449)
+const MAX_CMD_LEN: usize = 255;
+
+/// maximum length of a single command argument (RFC 2449)
+const MAX_ARG_LEN: usize = 255;
+
+/// reason a command cannot be sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MsgError {
+  /// the argument is empty
+  EmptyArgument,
+  /// the argument contains a control character, which could inject another command
+  ControlCharacter(u8),
+  /// the argument is longer than 255 octets
+  ArgumentTooLong(usize),
+  /// the whole command is longer than 255 octets
+  LineTooLong(usize),
+}
+
+impl std::fmt::Display for MsgError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::EmptyArgument => write!(f, "empty argument"),
+      Self::ControlCharacter(c) => write!(f, "argument contains control character 0x{:02x}", c),
+      Self::ArgumentTooLong(len) => write!(
+        f,
+        "argument is {} octets long, exceeding the limit of {} octets",
+        len, MAX_ARG_LEN
+      ),
+      Self::LineTooLong(len) => write!(
+        f,
+        "command is {} octets long, exceeding the limit of {} octets",
+        len, MAX_CMD_LEN
+      ),
+    }
+  }
+}
+
+impl std::error::Error for MsgError {}
+
+fn check_arg(arg: &str) -> std::result::Result<(), MsgError> {
+  if arg.is_empty() {
+    return Err(MsgError::EmptyArgument);
+  }
+  if let Some(c) = arg.bytes().find(|c| c.is_ascii_control()) {
+    return Err(MsgError::ControlCharacter(c));
+  }
+  if arg.len() > MAX_ARG_LEN {
+    return Err(MsgError::ArgumentTooLong(arg.len()));
+  }
+  Ok(())
+}
+
+/// builder of command lines, reusing one buffer
+///
+/// every builder method replaces the previous command and returns the new command line, ending
+/// with CRLF
+pub struct Msg {
+  buf: Vec<u8>,
+}
+
+impl Msg {
+  /// a builder whose buffer starts with room for `len` octets
+  pub fn with_maximum_len(len: usize) -> Self {
+    Self {
+      buf: Vec::<u8>::with_capacity(len),
+    }
+  }
+  /// the last command built
+  pub fn get_msg(&self) -> &[u8] {
+    self.buf.as_slice()
+  }
+  /// the last command built, as text
+  pub fn into_string(self) -> String {
+    String::from_utf8(self.buf).unwrap()
+  }
+  fn check_len(&mut self) -> Result<&[u8]> {
+    let len = self.buf.len();
+    if len > MAX_CMD_LEN {
+      self.buf.clear();
+      return Err(MsgError::LineTooLong(len).into());
+    }
+    Ok(self.get_msg())
+  }
+  /// `USER name`
+  pub fn user(&mut self, name: &str) -> Result<&[u8]> {
+    self.buf.clear();
+    check_arg(name)?;
+    write!(&mut self.buf, "USER {}\r\n", name)?;
+    self.check_len()
+  }
+  /// `PASS secret`
+  pub fn pass(&mut self, secret: &str) -> Result<&[u8]> {
+    self.buf.clear();
+    check_arg(secret)?;
+    write!(&mut self.buf, "PASS {}\r\n", secret)?;
+    self.check_len()
+  }
+  /// `STAT`
+  pub fn stat(&mut self) -> Result<&[u8]> {
+    self.buf.clear();
+    write!(&mut self.buf, "STAT\r\n")?;
+    Ok(self.get_msg())
+  }
+  /// `LIST [id]`
+  pub fn list(&mut self, id: Option<u64>) -> Result<&[u8]> {
+    self.buf.clear();
+    if let Some(id) = id {
+      write!(&mut self.buf, "LIST {}\r\n", id)?;
+    } else {
+      write!(&mut self.buf, "LIST\r\n")?;
+    }
+    Ok(self.get_msg())
+  }
+  /// `RETR msg`
+  pub fn retr(&mut self, msg: u64) -> Result<&[u8]> {
+    self.buf.clear();
+    write!(&mut self.buf, "RETR {}\r\n", msg)?;
+    Ok(self.get_msg())
+  }
+  /// `TOP msg lines`
+  pub fn top(&mut self, msg: u64, lines: u64) -> Result<&[u8]> {
+    self.buf.clear();
+    write!(&mut self.buf, "TOP {} {}\r\n", msg, lines)?;
+    Ok(self.get_msg())
+  }
+  /// `DELE msg`
+  pub fn dele(&mut self, msg: u64) -> Result<&[u8]> {
+    self.buf.clear();
+    write!(&mut self.buf, "DELE {}\r\n", msg)?;
+    Ok(self.get_msg())
+  }
+  /// `UIDL [id]`
+  pub fn uidl(&mut self, id: Option<u64>) -> Result<&[u8]> {
+    self.buf.clear();
+    if let Some(id) = id {
+      write!(&mut self.buf, "UIDL {}\r\n", id)?;
+    } else {
+      write!(&mut self.buf, "UIDL\r\n")?;
+    }
+    Ok(self.get_msg())
+  }
+  /// `AUTH EXTERNAL` (RFC 5034) with the initial response, authorizing as `authzid`, or as the
+  /// identity the server derives from the client certificate when `None`
+  pub fn auth_external(&mut self, authzid: Option<&str>) -> Result<&[u8]> {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    self.buf.clear();
+    match authzid {
+      Some(authzid) => {
+        check_arg(authzid)?;
+        write!(
+          &mut self.buf,
+          "AUTH EXTERNAL {}\r\n",
+          STANDARD.encode(authzid)
+        )?;
+      }
+      // a single `=` stands for an empty response
+      None => write!(&mut self.buf, "AUTH EXTERNAL =\r\n")?,
+    }
+    self.check_len()
+  }
+  /// `STLS`
+  pub fn stls(&mut self) -> Result<&[u8]> {
+    self.buf.clear();
+    write!(&mut self.buf, "STLS\r\n")?;
+    Ok(self.get_msg())
+  }
+  /// `QUIT`
+  pub fn quit(&mut self) -> Result<&[u8]> {
+    self.buf.clear();
+    write!(&mut self.buf, "QUIT\r\n")?;
+    Ok(self.get_msg())
+  }
+}
+
+impl Default for Msg {
+  fn default() -> Self {
+    Self::with_maximum_len(27)
+  }
+}
+
+#[test]
+fn test_msg_user_pass() {
+  let mut msg = Msg::default();
+  assert_eq!(msg.user("alice").unwrap(), b"USER alice\r\n");
+  assert_eq!(msg.pass("p@ss word").unwrap(), b"PASS p@ss word\r\n");
+
+  let long = "a".repeat(MAX_CMD_LEN - "USER \r\n".len());
+  assert!(msg.user(&long).is_ok());
+}
+
+#[test]
+fn test_msg_auth_external() {
+  let mut msg = Msg::default();
+  assert_eq!(msg.auth_external(None).unwrap(), b"AUTH EXTERNAL =\r\n");
+  assert_eq!(
+    msg.auth_external(Some("alice")).unwrap(),
+    b"AUTH EXTERNAL YWxpY2U=\r\n"
+  );
+  assert!(msg.auth_external(Some("alice\r\n")).is_err());
+  assert!(msg.auth_external(Some(&"a".repeat(200))).is_err());
+}
+
+#[test]
+fn test_msg_rejects_injection() {
+  let kind = |res: Result<&[u8]>| res.unwrap_err().downcast::<MsgError>().unwrap();
+
+  let mut msg = Msg::default();
+  assert_eq!(
+    kind(msg.user("alice\r\nDELE 1")),
+    MsgError::ControlCharacter(b'\r')
+  );
+  assert_eq!(
+    kind(msg.pass("secret\nDELE 1")),
+    MsgError::ControlCharacter(b'\n')
+  );
+  assert_eq!(kind(msg.user("alice\0")), MsgError::ControlCharacter(0));
+  assert_eq!(kind(msg.pass("\x7f")), MsgError::ControlCharacter(0x7f));
+  assert_eq!(kind(msg.user("")), MsgError::EmptyArgument);
+  assert_eq!(kind(msg.pass("")), MsgError::EmptyArgument);
+  assert!(msg.get_msg().is_empty());
+}
+
+#[test]
+fn test_msg_rejects_oversize() {
+  let kind = |res: Result<&[u8]>| res.unwrap_err().downcast::<MsgError>().unwrap();
+
+  let mut msg = Msg::default();
+  assert_eq!(
+    kind(msg.user(&"a".repeat(MAX_ARG_LEN + 1))),
+    MsgError::ArgumentTooLong(MAX_ARG_LEN + 1)
+  );
+  assert_eq!(
+    kind(msg.pass(&"a".repeat(MAX_ARG_LEN))),
+    MsgError::LineTooLong(MAX_ARG_LEN + "PASS \r\n".len())
+  );
+  assert!(msg.get_msg().is_empty());
+}
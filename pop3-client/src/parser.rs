@@ -0,0 +1,1324 @@
+//! parsers of POP3 responses and an incremental decoder built on them
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use memchr::memchr;
+use nom::{
+  branch::alt,
+  bytes::complete::{tag, tag_no_case, take_until, take_while1},
+  character::complete::{char, crlf, digit1, space0, space1},
+  combinator::{map_res, opt, value, verify},
+  sequence::{pair, preceded, separated_pair, terminated},
+  IResult,
+};
+use std::ops::Range;
+use tokio_util::codec::Decoder;
+
+use crate::ParseMode;
+
+/// for debug only
+#[allow(unused)]
+macro_rules! probe {
+  ($e:expr) => {
+    |msg| dbg!($e(msg))
+  };
+}
+
+/// outcome of parsing a response, `Err` holding the text of a negative status line
+type Parsed<'a, T> = IResult<&'a [u8], Result<T, &'a [u8]>>;
+
+/// scan listings, followed by the status text
+type ScanListings<'a> = (Vec<(u64, u64)>, &'a [u8]);
+
+/// a unique-id listing, followed by the status text
+type UniqueIdListing<'a> = (u64, &'a [u8], &'a [u8]);
+
+/// unique-id listings, followed by the status text
+type UniqueIdListings<'a> = (Vec<(u64, &'a [u8])>, &'a [u8]);
+
+/// parse a decimal number, failing instead of overflowing on values that do not fit in u64
+fn get_u64(msg: &[u8]) -> IResult<&[u8], u64> {
+  map_res(map_res(digit1, std::str::from_utf8), str::parse::<u64>)(msg)
+}
+
+#[test]
+fn test_get_u64() {
+  assert_eq!(get_u64("0".as_bytes()), Ok(("".as_bytes(), 0)));
+  assert_eq!(get_u64("123 456".as_bytes()), Ok((" 456".as_bytes(), 123)));
+  assert_eq!(
+    get_u64("18446744073709551615".as_bytes()),
+    Ok(("".as_bytes(), u64::MAX))
+  );
+  assert!(get_u64("18446744073709551616".as_bytes()).is_err());
+  assert!(get_u64("123456789012345678901234567890".as_bytes()).is_err());
+  assert!(get_u64("".as_bytes()).is_err());
+  assert!(get_u64("-1".as_bytes()).is_err());
+}
+
+/// take a line, without its line ending
+fn take_line(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], &[u8]> {
+  move |msg| match mode {
+    ParseMode::Strict => terminated(take_until("\r\n"), crlf)(msg),
+    ParseMode::Lenient => {
+      let (msg, line) = terminated(take_until("\n"), char('\n'))(msg)?;
+      Ok((msg, line.strip_suffix(b"\r").unwrap_or(line)))
+    }
+  }
+}
+
+/// take a line, including its line ending
+#[cfg(test)]
+fn take_line_with_crlf(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], &[u8]> {
+  move |msg| match mode {
+    ParseMode::Strict => nom::combinator::recognize(pair(take_until("\r\n"), crlf))(msg),
+    ParseMode::Lenient => nom::combinator::recognize(pair(take_until("\n"), char('\n')))(msg),
+  }
+}
+
+fn is_ok(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], bool> {
+  move |msg| match mode {
+    ParseMode::Strict => alt((value(true, tag("+OK")), value(false, tag("-ERR"))))(msg),
+    ParseMode::Lenient => alt((
+      value(true, tag_no_case("+OK")),
+      value(false, tag_no_case("-ERR")),
+    ))(msg),
+  }
+}
+
+#[test]
+fn test_is_ok() {
+  let is_ok = is_ok(ParseMode::Strict);
+
+  assert_eq!(is_ok("+OK".as_bytes()), Ok(("".as_bytes(), true)));
+  assert_eq!(is_ok("-ERR".as_bytes()), Ok(("".as_bytes(), false)));
+  assert_eq!(
+    is_ok("+OK some other message".as_bytes()),
+    Ok((" some other message".as_bytes(), true))
+  );
+  assert_eq!(
+    is_ok("-ERR some other message".as_bytes()),
+    Ok((" some other message".as_bytes(), false))
+  );
+  assert!(is_ok("some random message".as_bytes()).is_err());
+  assert!(is_ok("+ok".as_bytes()).is_err());
+}
+
+#[test]
+fn test_is_ok_lenient() {
+  let is_ok = is_ok(ParseMode::Lenient);
+
+  assert_eq!(is_ok("+OK".as_bytes()), Ok(("".as_bytes(), true)));
+  assert_eq!(is_ok("+ok".as_bytes()), Ok(("".as_bytes(), true)));
+  assert_eq!(is_ok("+Ok".as_bytes()), Ok(("".as_bytes(), true)));
+  assert_eq!(is_ok("-err".as_bytes()), Ok(("".as_bytes(), false)));
+  assert!(is_ok("some random message".as_bytes()).is_err());
+}
+
+fn get_status_line(
+  mode: ParseMode,
+  trim_ok: bool,
+  trim_err: bool,
+) -> impl Fn(&[u8]) -> Parsed<'_, &[u8]> {
+  move |msg| {
+    let (msg, (ok, status_msg)) = pair(is_ok(mode), take_line(mode))(msg)?;
+    let (trimmed_status_msg, _) = space0(status_msg)?;
+    Ok((
+      msg,
+      if ok {
+        Ok(if trim_ok {
+          trimmed_status_msg
+        } else {
+          status_msg
+        })
+      } else {
+        Err(if trim_err {
+          trimmed_status_msg
+        } else {
+          status_msg
+        })
+      },
+    ))
+  }
+}
+
+#[test]
+fn test_get_status_line() {
+  let get_status_line = get_status_line(ParseMode::Strict, true, true);
+
+  assert!(get_status_line("+OK".as_bytes()).is_err());
+  assert!(get_status_line("-ERR".as_bytes()).is_err());
+  assert!(get_status_line("some random message".as_bytes()).is_err());
+  assert!(get_status_line("some random message\r\n".as_bytes()).is_err());
+
+  assert_eq!(
+    get_status_line("+OK\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok("".as_bytes())))
+  );
+  assert_eq!(
+    get_status_line("+OK \r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok("".as_bytes())))
+  );
+  assert_eq!(
+    get_status_line("+OKsome message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok("some message".as_bytes())))
+  );
+  assert_eq!(
+    get_status_line("+OK some message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok("some message".as_bytes())))
+  );
+
+  assert_eq!(
+    get_status_line("-ERR\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("".as_bytes())))
+  );
+  assert_eq!(
+    get_status_line("-ERR \r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("".as_bytes())))
+  );
+  assert_eq!(
+    get_status_line("-ERRsome message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("some message".as_bytes())))
+  );
+  assert_eq!(
+    get_status_line("-ERR some message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("some message".as_bytes())))
+  );
+}
+
+fn get_line(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], Option<&[u8]>> {
+  move |msg| {
+    let (msg, (octet, line)) = pair(opt(char('.')), take_line(mode))(msg)?;
+    if octet.is_some() && line.is_empty() {
+      Ok((msg, None))
+    } else {
+      Ok((msg, Some(line)))
+    }
+  }
+}
+
+#[cfg(test)]
+fn get_line_with_crlf(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], Option<&[u8]>> {
+  move |msg| {
+    let (msg, (octet, line)) = pair(opt(char('.')), take_line_with_crlf(mode))(msg)?;
+    if octet.is_some()
+      && (line == &b"\r\n"[..] || (mode == ParseMode::Lenient && line == &b"\n"[..]))
+    {
+      Ok((msg, None))
+    } else {
+      Ok((msg, Some(line)))
+    }
+  }
+}
+
+#[test]
+fn test_get_line() {
+  let get_line = get_line(ParseMode::Strict);
+
+  assert!(get_line("abcd 1234".as_bytes()).is_err());
+  assert!(get_line("abcd 1234\r".as_bytes()).is_err());
+  assert!(get_line("abcd 1234\n".as_bytes()).is_err());
+
+  assert_eq!(
+    get_line("abcd 1234\r\n".as_bytes()),
+    Ok(("".as_bytes(), Some("abcd 1234".as_bytes())))
+  );
+  assert_eq!(
+    get_line("abcd 1234\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some("abcd 1234".as_bytes())))
+  );
+
+  assert_eq!(
+    get_line(".abcd 1234\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some("abcd 1234".as_bytes())))
+  );
+  assert_eq!(
+    get_line("..abcd 1234\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some(".abcd 1234".as_bytes())))
+  );
+  assert_eq!(
+    get_line("..\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some(".".as_bytes())))
+  );
+  assert_eq!(
+    get_line("\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some("".as_bytes())))
+  );
+  assert_eq!(
+    get_line(".\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), None))
+  );
+}
+
+#[test]
+fn test_get_line_lenient() {
+  let get_line = get_line(ParseMode::Lenient);
+
+  assert!(get_line("abcd 1234".as_bytes()).is_err());
+  assert!(get_line("abcd 1234\r".as_bytes()).is_err());
+
+  assert_eq!(
+    get_line("abcd 1234\n".as_bytes()),
+    Ok(("".as_bytes(), Some("abcd 1234".as_bytes())))
+  );
+  assert_eq!(
+    get_line("abcd 1234\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some("abcd 1234".as_bytes())))
+  );
+  assert_eq!(
+    get_line("..abcd 1234\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some(".abcd 1234".as_bytes())))
+  );
+  assert_eq!(
+    get_line(".\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), None))
+  );
+  assert_eq!(
+    get_line(".\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), None))
+  );
+}
+
+#[test]
+fn test_get_line_with_crlf() {
+  let get_line_with_crlf = get_line_with_crlf(ParseMode::Strict);
+
+  assert!(get_line_with_crlf("abcd 1234".as_bytes()).is_err());
+  assert!(get_line_with_crlf("abcd 1234\r".as_bytes()).is_err());
+  assert!(get_line_with_crlf("abcd 1234\n".as_bytes()).is_err());
+
+  assert_eq!(
+    get_line_with_crlf("abcd 1234\r\n".as_bytes()),
+    Ok(("".as_bytes(), Some("abcd 1234\r\n".as_bytes())))
+  );
+  assert_eq!(
+    get_line_with_crlf("abcd 1234\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some("abcd 1234\r\n".as_bytes())))
+  );
+
+  assert_eq!(
+    get_line_with_crlf(".abcd 1234\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some("abcd 1234\r\n".as_bytes())))
+  );
+  assert_eq!(
+    get_line_with_crlf("..abcd 1234\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some(".abcd 1234\r\n".as_bytes())))
+  );
+  assert_eq!(
+    get_line_with_crlf("..\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some(".\r\n".as_bytes())))
+  );
+  assert_eq!(
+    get_line_with_crlf("\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), Some("\r\n".as_bytes())))
+  );
+  assert_eq!(
+    get_line_with_crlf(".\r\nabcd 1234".as_bytes()),
+    Ok(("abcd 1234".as_bytes(), None))
+  );
+}
+
+fn get_stat_result(mode: ParseMode) -> impl Fn(&[u8]) -> Parsed<'_, (u64, u64, &[u8])> {
+  move |msg| {
+    let (rest, stat) = get_status_line(mode, false, true)(msg)?;
+    match stat {
+      Ok(stat) => {
+        let (msg, (msg_num, maildrop_bytes)) = match mode {
+          ParseMode::Strict => {
+            pair(preceded(char(' '), get_u64), preceded(char(' '), get_u64))(stat)?
+          }
+          ParseMode::Lenient => pair(preceded(space1, get_u64), preceded(space1, get_u64))(stat)?,
+        };
+        Ok((rest, Ok((msg_num, maildrop_bytes, msg))))
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+}
+
+#[test]
+fn test_get_stat_result() {
+  let get_stat_result = get_stat_result(ParseMode::Strict);
+
+  assert!(get_stat_result("+OK\r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK\r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK \r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK  \r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK 123 \r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK  123 456\r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK 123  456\r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK 123456789012345678901234567890 456\r\n".as_bytes()).is_err());
+  assert!(get_stat_result("+OK 123 456789012345678901234567890123\r\n".as_bytes()).is_err());
+  assert_eq!(
+    get_stat_result("+OK 123 456\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok((123, 456, "".as_bytes()))))
+  );
+  assert_eq!(
+    get_stat_result("+OK 123 456 \r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok((123, 456, " ".as_bytes()))))
+  );
+  assert_eq!(
+    get_stat_result("+OK 123 456 additional message\r\nrest".as_bytes()),
+    Ok((
+      "rest".as_bytes(),
+      Ok((123, 456, " additional message".as_bytes()))
+    ))
+  );
+
+  assert_eq!(
+    get_stat_result("-ERR\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("".as_bytes())))
+  );
+  assert_eq!(
+    get_stat_result("-ERR error message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("error message".as_bytes())))
+  );
+}
+
+fn get_scan_listing(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], (u64, u64)> {
+  move |msg| match mode {
+    ParseMode::Strict => separated_pair(get_u64, char(' '), get_u64)(msg),
+    ParseMode::Lenient => preceded(space0, separated_pair(get_u64, space1, get_u64))(msg),
+  }
+}
+
+fn get_single_list_result(mode: ParseMode) -> impl Fn(&[u8]) -> Parsed<'_, (u64, u64, &[u8])> {
+  move |msg| {
+    let (rest, list) = get_status_line(mode, true, true)(msg)?;
+    match list {
+      Ok(list) => {
+        let (msg, (mail_num, mail_bytes)) = get_scan_listing(mode)(list)?;
+        Ok((rest, Ok((mail_num, mail_bytes, msg))))
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+}
+
+fn get_list_result(mode: ParseMode) -> impl Fn(&[u8]) -> Parsed<'_, ScanListings<'_>> {
+  move |msg| {
+    let (mut rest, list) = get_status_line(mode, true, true)(msg)?;
+    match list {
+      Ok(msg) => {
+        let mut list = Vec::<(u64, u64)>::new();
+        loop {
+          let (new_rest, scan_listing) = get_line(mode)(rest)?;
+          rest = new_rest;
+          if let Some(scan_listing) = scan_listing {
+            let (_discarded_additional_msg, scan_listing) = get_scan_listing(mode)(scan_listing)?;
+            list.push(scan_listing)
+          } else {
+            break Ok((rest, Ok((list, msg))));
+          }
+        }
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+}
+
+#[test]
+fn test_get_single_list_result() {
+  let get_single_list_result = get_single_list_result(ParseMode::Strict);
+
+  assert!(get_single_list_result("+OK\r\n".as_bytes()).is_err());
+  assert!(get_single_list_result("+OK \r\n".as_bytes()).is_err());
+  assert!(get_single_list_result("+OK  \r\n".as_bytes()).is_err());
+  assert!(get_single_list_result("+OK 123\r\n".as_bytes()).is_err());
+  assert!(get_single_list_result("+OK 123 \r\n".as_bytes()).is_err());
+  assert!(get_single_list_result("+OK 123  456\r\n".as_bytes()).is_err());
+  assert!(get_single_list_result("+OK 123456789012345678901234567890 456\r\n".as_bytes()).is_err());
+  assert_eq!(
+    get_single_list_result("+OK 123 456\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok((123, 456, "".as_bytes()))))
+  );
+  assert_eq!(
+    get_single_list_result("+OK  123 456 \r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok((123, 456, " ".as_bytes()))))
+  );
+  assert_eq!(
+    get_single_list_result("+OK 123 456 additional message\r\nrest".as_bytes()),
+    Ok((
+      "rest".as_bytes(),
+      Ok((123, 456, " additional message".as_bytes()))
+    ))
+  );
+
+  assert_eq!(
+    get_single_list_result("-ERR\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("".as_bytes())))
+  );
+  assert_eq!(
+    get_single_list_result("-ERR error message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("error message".as_bytes())))
+  );
+}
+
+#[test]
+fn test_get_list_result() {
+  let get_list_result = get_list_result(ParseMode::Strict);
+  let get_single_list_result = get_single_list_result(ParseMode::Strict);
+
+  assert!(get_list_result("+OK\r\n".as_bytes()).is_err());
+  assert!(get_list_result("+OK additional message".as_bytes()).is_err());
+  assert!(get_list_result("+OK additional message\r\nrandom message".as_bytes()).is_err());
+  assert!(get_list_result(
+    "+OK additional message\r\n1 123456789012345678901234567890\r\n.\r\n".as_bytes()
+  )
+  .is_err());
+
+  assert_eq!(
+    get_list_result("+OK additional message\r\n.\r\nrest".as_bytes()),
+    Ok((
+      "rest".as_bytes(),
+      Ok((vec![], "additional message".as_bytes()))
+    ))
+  );
+
+  assert_eq!(
+    get_list_result("+OK additional message\r\n123 456\r\n.\r\nrest".as_bytes()),
+    Ok((
+      "rest".as_bytes(),
+      Ok((vec![(123, 456)], "additional message".as_bytes()))
+    ))
+  );
+
+  assert_eq!(
+    get_list_result("+OK additional message\r\n12 34\r\n56 78\r\n.\r\nrest".as_bytes()),
+    Ok((
+      "rest".as_bytes(),
+      Ok((vec![(12, 34), (56, 78)], "additional message".as_bytes()))
+    ))
+  );
+
+  assert_eq!(
+    get_single_list_result("-ERR\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("".as_bytes())))
+  );
+  assert_eq!(
+    get_single_list_result("-ERR error message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("error message".as_bytes())))
+  );
+}
+
+fn is_unique_id_char(c: u8) -> bool {
+  (0x21..=0x7e).contains(&c)
+}
+
+/// a unique-id is 1 to 70 printable characters (RFC 1939), though some servers send longer ones
+fn get_unique_id(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], &[u8]> {
+  move |msg| match mode {
+    ParseMode::Strict => verify(take_while1(is_unique_id_char), |id: &[u8]| id.len() <= 70)(msg),
+    ParseMode::Lenient => take_while1(is_unique_id_char)(msg),
+  }
+}
+
+fn get_unique_id_listing(mode: ParseMode) -> impl Fn(&[u8]) -> IResult<&[u8], (u64, &[u8])> {
+  move |msg| match mode {
+    ParseMode::Strict => separated_pair(get_u64, char(' '), get_unique_id(mode))(msg),
+    ParseMode::Lenient => {
+      preceded(space0, separated_pair(get_u64, space1, get_unique_id(mode)))(msg)
+    }
+  }
+}
+
+fn get_single_uidl_result(mode: ParseMode) -> impl Fn(&[u8]) -> Parsed<'_, UniqueIdListing<'_>> {
+  move |msg| {
+    let (rest, uidl) = get_status_line(mode, true, true)(msg)?;
+    match uidl {
+      Ok(uidl) => {
+        let (msg, (mail_num, unique_id)) = get_unique_id_listing(mode)(uidl)?;
+        Ok((rest, Ok((mail_num, unique_id, msg))))
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+}
+
+fn get_uidl_result(mode: ParseMode) -> impl Fn(&[u8]) -> Parsed<'_, UniqueIdListings<'_>> {
+  move |msg| {
+    let (mut rest, uidl) = get_status_line(mode, true, true)(msg)?;
+    match uidl {
+      Ok(msg) => {
+        let mut list = Vec::<(u64, &[u8])>::new();
+        loop {
+          let (new_rest, listing) = get_line(mode)(rest)?;
+          rest = new_rest;
+          if let Some(listing) = listing {
+            let (_discarded_additional_msg, listing) = get_unique_id_listing(mode)(listing)?;
+            list.push(listing)
+          } else {
+            break Ok((rest, Ok((list, msg))));
+          }
+        }
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+}
+
+#[test]
+fn test_get_single_uidl_result() {
+  let get_single_uidl_result = get_single_uidl_result(ParseMode::Strict);
+
+  assert!(get_single_uidl_result("+OK\r\n".as_bytes()).is_err());
+  assert!(get_single_uidl_result("+OK 1\r\n".as_bytes()).is_err());
+  assert!(get_single_uidl_result("+OK 1 \r\n".as_bytes()).is_err());
+  assert!(get_single_uidl_result("+OK 1  whqtswO00WBw418f9t5JxYwZ\r\n".as_bytes()).is_err());
+  assert_eq!(
+    get_single_uidl_result("+OK 2 QhdPYR:00WBw1Ph7x7\r\nrest".as_bytes()),
+    Ok((
+      "rest".as_bytes(),
+      Ok((2, "QhdPYR:00WBw1Ph7x7".as_bytes(), "".as_bytes()))
+    ))
+  );
+  assert_eq!(
+    get_single_uidl_result("-ERR no such message\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("no such message".as_bytes())))
+  );
+}
+
+#[test]
+fn test_get_uidl_result() {
+  let get_uidl_result = get_uidl_result(ParseMode::Strict);
+
+  assert!(get_uidl_result("+OK\r\n1 \r\n.\r\n".as_bytes()).is_err());
+  assert!(get_uidl_result(format!("+OK\r\n1 {}\r\n.\r\n", "a".repeat(71)).as_bytes()).is_err());
+  assert!(get_uidl_result(format!("+OK\r\n1 {}\r\n.\r\n", "a".repeat(70)).as_bytes()).is_ok());
+
+  assert_eq!(
+    get_uidl_result("+OK\r\n.\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Ok((vec![], "".as_bytes()))))
+  );
+  assert_eq!(
+    get_uidl_result(
+      "+OK unique-id listing follows\r\n1 whqtswO00WBw418f9t5JxYwZ\r\n2 QhdPYR:00WBw1Ph7x7\r\n.\r\nrest"
+        .as_bytes()
+    ),
+    Ok((
+      "rest".as_bytes(),
+      Ok((
+        vec![
+          (1, "whqtswO00WBw418f9t5JxYwZ".as_bytes()),
+          (2, "QhdPYR:00WBw1Ph7x7".as_bytes())
+        ],
+        "unique-id listing follows".as_bytes()
+      ))
+    ))
+  );
+  assert_eq!(
+    get_uidl_result("-ERR\r\nrest".as_bytes()),
+    Ok(("rest".as_bytes(), Err("".as_bytes())))
+  );
+}
+
+/// the mail of a whole response to RETR, parsed independently of [`ResponseDecoder`] to check it
+#[cfg(test)]
+fn get_retr_result(mode: ParseMode) -> impl Fn(&[u8]) -> Parsed<'_, (Vec<u8>, &[u8])> {
+  move |msg| {
+    let (mut rest, retr) = get_status_line(mode, true, true)(msg)?;
+    match retr {
+      Ok(msg) => {
+        let mut mail = Vec::<u8>::new();
+        loop {
+          let (new_rest, line) = get_line_with_crlf(mode)(rest)?;
+          rest = new_rest;
+          if let Some(line) = line {
+            mail.extend_from_slice(line);
+          } else {
+            break Ok((rest, Ok((mail, msg))));
+          }
+        }
+      }
+      Err(msg) => Ok((rest, Err(msg))),
+    }
+  }
+}
+
+/// progress of a response being received line by line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseState {
+  /// waiting for the status line
+  Status { multiline: bool },
+  /// waiting for data lines or the lone-dot terminator
+  Data,
+  /// the whole response has been received
+  Done,
+}
+
+impl ResponseState {
+  fn new(multiline: bool) -> Self {
+    Self::Status { multiline }
+  }
+
+  /// whether `line` holds a whole line, including its line ending
+  fn is_line_complete(line: &[u8], mode: ParseMode) -> bool {
+    match mode {
+      ParseMode::Strict => line.ends_with(b"\r\n"),
+      ParseMode::Lenient => line.ends_with(b"\n"),
+    }
+  }
+
+  /// advance past a complete line
+  fn next(self, line: &[u8], mode: ParseMode) -> Self {
+    match self {
+      // only a positive status line is followed by data lines
+      Self::Status { multiline: true } if matches!(is_ok(mode)(line), Ok((_, true))) => Self::Data,
+      Self::Status { .. } => Self::Done,
+      Self::Data if Self::is_terminator(line, mode) => Self::Done,
+      Self::Data => Self::Data,
+      Self::Done => Self::Done,
+    }
+  }
+
+  /// whether the complete data line `line` is the lone dot ending a multi-line response
+  fn is_terminator(line: &[u8], mode: ParseMode) -> bool {
+    match mode {
+      ParseMode::Strict => line == b".\r\n",
+      ParseMode::Lenient => line == b".\r\n" || line == b".\n",
+    }
+  }
+}
+
+#[test]
+fn test_response_state() {
+  let feed = |multiline: bool, mode: ParseMode, lines: &[&str]| {
+    lines
+      .iter()
+      .map(|line| line.as_bytes())
+      .filter(|line| ResponseState::is_line_complete(line, mode))
+      .fold(ResponseState::new(multiline), |state, line| {
+        state.next(line, mode)
+      })
+  };
+
+  let strict = ParseMode::Strict;
+  assert_eq!(feed(false, strict, &["+OK\r\n"]), ResponseState::Done);
+  assert_eq!(feed(false, strict, &["-ERR\r\n"]), ResponseState::Done);
+  assert_eq!(
+    feed(false, strict, &["+OK\n"]),
+    ResponseState::Status { multiline: false }
+  );
+
+  assert_eq!(feed(true, strict, &["+OK\r\n"]), ResponseState::Data);
+  assert_eq!(
+    feed(true, strict, &["+OK\r\n", ".\r\n"]),
+    ResponseState::Done
+  );
+  assert_eq!(
+    feed(true, strict, &["-ERR no such message\r\n"]),
+    ResponseState::Done
+  );
+  assert_eq!(
+    feed(true, strict, &["+OK\r\n", "..\r\n", ". \r\n", "\r\n"]),
+    ResponseState::Data
+  );
+  assert_eq!(
+    feed(true, strict, &["+OK\r\n", "1 2\r\n", ".\n", ".\r\n"]),
+    ResponseState::Done
+  );
+  assert_eq!(feed(true, strict, &["+ok\r\n"]), ResponseState::Done);
+
+  let lenient = ParseMode::Lenient;
+  assert_eq!(feed(false, lenient, &["+OK\n"]), ResponseState::Done);
+  assert_eq!(feed(true, lenient, &["+ok\n"]), ResponseState::Data);
+  assert_eq!(feed(true, lenient, &["+ok\n", ".\n"]), ResponseState::Done);
+  assert_eq!(
+    feed(true, lenient, &["+OK\r\n", ".\r\n"]),
+    ResponseState::Done
+  );
+}
+
+/// kind of response expected from the server, determined by the command sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expect {
+  /// a single status line, in reply to USER, PASS, DELE, QUIT or the greeting
+  Status,
+  /// reply to STAT
+  Stat,
+  /// reply to LIST with an argument
+  ScanListing,
+  /// reply to LIST without argument
+  ScanListings,
+  /// reply to UIDL with an argument
+  UniqueIdListing,
+  /// reply to UIDL without argument
+  UniqueIdListings,
+  /// reply to RETR or TOP
+  Mail,
+}
+
+impl Expect {
+  fn is_multiline(self) -> bool {
+    matches!(
+      self,
+      Self::ScanListings | Self::UniqueIdListings | Self::Mail
+    )
+  }
+}
+
+/// a decoded response, with its text referring to the receive buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response<T = Bytes> {
+  /// text of a positive status line
+  Status(T),
+  /// number of messages and size of the maildrop, followed by the status text
+  Stat(u64, u64, T),
+  /// message number and size, followed by the status text
+  ScanListing(u64, u64, T),
+  /// message numbers and sizes, followed by the status text
+  ScanListings(Vec<(u64, u64)>, T),
+  /// message number and unique-id, followed by the status text
+  UniqueIdListing(u64, T, T),
+  /// message numbers and unique-ids, followed by the status text
+  UniqueIdListings(Vec<(u64, T)>, T),
+  /// mail with dot-stuffing removed, followed by the status message
+  Mail(T, T),
+  /// negative status message in reply to any command
+  Err(T),
+}
+
+impl<T> Response<T> {
+  fn map<U>(self, f: impl Fn(T) -> U) -> Response<U> {
+    match self {
+      Self::Status(msg) => Response::Status(f(msg)),
+      Self::Stat(msg_num, maildrop_bytes, msg) => Response::Stat(msg_num, maildrop_bytes, f(msg)),
+      Self::ScanListing(mail_num, mail_bytes, msg) => {
+        Response::ScanListing(mail_num, mail_bytes, f(msg))
+      }
+      Self::ScanListings(list, msg) => Response::ScanListings(list, f(msg)),
+      Self::UniqueIdListing(mail_num, unique_id, msg) => {
+        Response::UniqueIdListing(mail_num, f(unique_id), f(msg))
+      }
+      Self::UniqueIdListings(list, msg) => Response::UniqueIdListings(
+        list
+          .into_iter()
+          .map(|(mail_num, unique_id)| (mail_num, f(unique_id)))
+          .collect(),
+        f(msg),
+      ),
+      Self::Mail(mail, msg) => Response::Mail(f(mail), f(msg)),
+      Self::Err(msg) => Response::Err(f(msg)),
+    }
+  }
+}
+
+impl Response {
+  /// the error of a negative or mismatched response
+  pub fn into_error(self) -> anyhow::Error {
+    match self {
+      Self::Err(msg) => anyhow! {String::from_utf8_lossy(&msg).to_string()},
+      _ => anyhow! {"unexpected response"},
+    }
+  }
+
+  pub(crate) fn into_status(self) -> Result<String> {
+    match self {
+      Self::Status(msg) => Ok(text(msg)),
+      response => Err(response.into_error()),
+    }
+  }
+
+  pub(crate) fn into_stat(self) -> Result<(u64, u64, String)> {
+    match self {
+      Self::Stat(msg_num, maildrop_bytes, msg) => Ok((msg_num, maildrop_bytes, text(msg))),
+      response => Err(response.into_error()),
+    }
+  }
+
+  pub(crate) fn into_list(self) -> Result<(Vec<(u64, u64)>, String)> {
+    match self {
+      Self::ScanListing(id, size, msg) => Ok((vec![(id, size)], text(msg))),
+      Self::ScanListings(list, msg) => Ok((list, text(msg))),
+      response => Err(response.into_error()),
+    }
+  }
+
+  pub(crate) fn into_mail(self) -> Result<(Bytes, String)> {
+    match self {
+      Self::Mail(mail, msg) => Ok((mail, text(msg))),
+      response => Err(response.into_error()),
+    }
+  }
+
+  pub(crate) fn into_uidl(self) -> Result<(Vec<(u64, String)>, String)> {
+    match self {
+      Self::UniqueIdListing(id, unique_id, msg) => Ok((vec![(id, text(unique_id))], text(msg))),
+      Self::UniqueIdListings(list, msg) => Ok((
+        list
+          .into_iter()
+          .map(|(id, unique_id)| (id, text(unique_id)))
+          .collect(),
+        text(msg),
+      )),
+      response => Err(response.into_error()),
+    }
+  }
+}
+
+fn text(bytes: Bytes) -> String {
+  String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// incremental decoder of server responses
+///
+/// received bytes are scanned only once, and a response is yielded as soon as its last line is
+/// complete, without copying its text out of the receive buffer
+pub struct ResponseDecoder {
+  mode: ParseMode,
+  expect: Expect,
+  state: ResponseState,
+  line_start: usize,
+  scanned: usize,
+}
+
+impl ResponseDecoder {
+  /// a decoder expecting a status line, such as the greeting
+  pub fn new(mode: ParseMode) -> Self {
+    Self {
+      mode,
+      expect: Expect::Status,
+      state: ResponseState::new(false),
+      line_start: 0,
+      scanned: 0,
+    }
+  }
+
+  /// how strictly responses are checked
+  pub fn mode(&self) -> ParseMode {
+    self.mode
+  }
+
+  /// change how strictly the following responses are checked
+  pub fn set_mode(&mut self, mode: ParseMode) {
+    self.mode = mode;
+  }
+
+  /// set the kind of the following responses, discarding progress on a partial response
+  pub fn expect(&mut self, expect: Expect) {
+    self.expect = expect;
+    self.state = ResponseState::new(expect.is_multiline());
+    self.line_start = 0;
+    self.scanned = 0;
+  }
+
+  /// scan newly received bytes, returning the length of the response once it is complete
+  fn scan(&mut self, buf: &[u8]) -> Option<usize> {
+    while let Some(pos) = memchr(b'\n', &buf[self.scanned..]) {
+      self.scanned += pos + 1;
+      let line = &buf[self.line_start..self.scanned];
+      if ResponseState::is_line_complete(line, self.mode) {
+        self.state = self.state.next(line, self.mode);
+        self.line_start = self.scanned;
+        if self.state == ResponseState::Done {
+          return Some(self.scanned);
+        }
+      }
+    }
+    self.scanned = buf.len();
+    None
+  }
+}
+
+impl Decoder for ResponseDecoder {
+  type Item = Response;
+  type Error = anyhow::Error;
+
+  fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Response>> {
+    match self.scan(buf) {
+      Some(len) => {
+        self.expect(self.expect);
+        decode_frame(buf.split_to(len), self.expect, self.mode).map(Some)
+      }
+      None => Ok(None),
+    }
+  }
+}
+
+fn span(frame: &[u8], part: &[u8]) -> Range<usize> {
+  let start = part.as_ptr() as usize - frame.as_ptr() as usize;
+  start..start + part.len()
+}
+
+/// remove dot-stuffing from the data lines starting at `start` in place, returning the end of
+/// the unstuffed data
+fn unstuff(frame: &mut [u8], start: usize, mode: ParseMode) -> Result<usize> {
+  let (mut read, mut write) = (start, start);
+  loop {
+    let mut end = read;
+    loop {
+      match memchr(b'\n', &frame[end..]) {
+        Some(pos) => end += pos + 1,
+        None => return Err(anyhow! {"incomplete response"}),
+      }
+      if ResponseState::is_line_complete(&frame[read..end], mode) {
+        break;
+      }
+    }
+    let line = &frame[read..end];
+    if ResponseState::is_terminator(line, mode) {
+      break Ok(write);
+    }
+    let octet = usize::from(line[0] == b'.');
+    // nothing needs to move until the first dot-stuffed line
+    if read + octet != write {
+      frame.copy_within(read + octet..end, write);
+    }
+    write += end - read - octet;
+    read = end;
+  }
+}
+
+fn decode_frame(mut frame: BytesMut, expect: Expect, mode: ParseMode) -> Result<Response> {
+  let response = match expect {
+    Expect::Status => match get_status_line(mode, true, true)(&frame) {
+      Ok((_, Ok(msg))) => Response::Status(span(&frame, msg)),
+      Ok((_, Err(msg))) => Response::Err(span(&frame, msg)),
+      Err(err) => return Err(anyhow! {err.to_string()}),
+    },
+    Expect::Stat => match get_stat_result(mode)(&frame) {
+      Ok((_, Ok((msg_num, maildrop_bytes, msg)))) => {
+        Response::Stat(msg_num, maildrop_bytes, span(&frame, msg))
+      }
+      Ok((_, Err(msg))) => Response::Err(span(&frame, msg)),
+      Err(err) => return Err(anyhow! {err.to_string()}),
+    },
+    Expect::ScanListing => match get_single_list_result(mode)(&frame) {
+      Ok((_, Ok((mail_num, mail_bytes, msg)))) => {
+        Response::ScanListing(mail_num, mail_bytes, span(&frame, msg))
+      }
+      Ok((_, Err(msg))) => Response::Err(span(&frame, msg)),
+      Err(err) => return Err(anyhow! {err.to_string()}),
+    },
+    Expect::ScanListings => match get_list_result(mode)(&frame) {
+      Ok((_, Ok((list, msg)))) => Response::ScanListings(list, span(&frame, msg)),
+      Ok((_, Err(msg))) => Response::Err(span(&frame, msg)),
+      Err(err) => return Err(anyhow! {err.to_string()}),
+    },
+    Expect::UniqueIdListing => match get_single_uidl_result(mode)(&frame) {
+      Ok((_, Ok((mail_num, unique_id, msg)))) => {
+        Response::UniqueIdListing(mail_num, span(&frame, unique_id), span(&frame, msg))
+      }
+      Ok((_, Err(msg))) => Response::Err(span(&frame, msg)),
+      Err(err) => return Err(anyhow! {err.to_string()}),
+    },
+    Expect::UniqueIdListings => match get_uidl_result(mode)(&frame) {
+      Ok((_, Ok((list, msg)))) => Response::UniqueIdListings(
+        list
+          .into_iter()
+          .map(|(mail_num, unique_id)| (mail_num, span(&frame, unique_id)))
+          .collect(),
+        span(&frame, msg),
+      ),
+      Ok((_, Err(msg))) => Response::Err(span(&frame, msg)),
+      Err(err) => return Err(anyhow! {err.to_string()}),
+    },
+    Expect::Mail => match get_status_line(mode, true, true)(&frame) {
+      Ok((rest, Ok(msg))) => {
+        let (msg, start) = (span(&frame, msg), frame.len() - rest.len());
+        let end = unstuff(&mut frame, start, mode)?;
+        Response::Mail(start..end, msg)
+      }
+      Ok((_, Err(msg))) => Response::Err(span(&frame, msg)),
+      Err(err) => return Err(anyhow! {err.to_string()}),
+    },
+  };
+  let frame = frame.freeze();
+  Ok(response.map(|range| frame.slice(range)))
+}
+
+#[test]
+fn test_response_decoder() {
+  let mut decoder = ResponseDecoder::new(ParseMode::Strict);
+  let mut buf = BytesMut::new();
+
+  decoder.expect(Expect::Status);
+  let mut responses = vec![];
+  for c in b"+OK POP3 server ready\r\n-ERR invalid password\r\n".iter() {
+    buf.extend_from_slice(&[*c]);
+    responses.extend(decoder.decode(&mut buf).unwrap());
+  }
+  assert_eq!(
+    responses,
+    vec![
+      Response::Status(Bytes::from("POP3 server ready")),
+      Response::Err(Bytes::from("invalid password"))
+    ]
+  );
+  assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+
+  decoder.expect(Expect::Stat);
+  buf.extend_from_slice(b"+OK 2 320\r\n");
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::Stat(2, 320, Bytes::new()))
+  );
+
+  decoder.expect(Expect::ScanListing);
+  buf.extend_from_slice(b"+OK 2 200\r\n");
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::ScanListing(2, 200, Bytes::new()))
+  );
+
+  decoder.expect(Expect::ScanListings);
+  buf.extend_from_slice(b"+OK\r\n.\r\n+OK 2 messages\r\n1 120\r\n2 200\r\n.");
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::ScanListings(vec![], Bytes::new()))
+  );
+  assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+  buf.extend_from_slice(b"\r\n-ERR no such message\r\n");
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::ScanListings(
+      vec![(1, 120), (2, 200)],
+      Bytes::from("2 messages")
+    ))
+  );
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::Err(Bytes::from("no such message")))
+  );
+
+  decoder.expect(Expect::Mail);
+  buf.extend_from_slice(b"+OK\r\nSubject: a\r\n\r\n..\r\n...b\r\n.\r\n");
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::Mail(
+      Bytes::from("Subject: a\r\n\r\n.\r\n..b\r\n"),
+      Bytes::new()
+    ))
+  );
+  assert!(buf.is_empty());
+
+  decoder.expect(Expect::Stat);
+  buf.extend_from_slice(b"+OK two 320\r\n+OK 2 320\r\n");
+  assert!(decoder.decode(&mut buf).is_err());
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::Stat(2, 320, Bytes::new()))
+  );
+}
+
+#[test]
+fn test_response_decoder_lenient() {
+  let mut decoder = ResponseDecoder::new(ParseMode::Lenient);
+  let mut buf = BytesMut::new();
+
+  decoder.expect(Expect::Mail);
+  buf.extend_from_slice(b"+ok\nSubject: a\n\n..b\r\n.\n");
+  assert_eq!(
+    decoder.decode(&mut buf).unwrap(),
+    Some(Response::Mail(
+      Bytes::from("Subject: a\n\n.b\r\n"),
+      Bytes::new()
+    ))
+  );
+}
+
+/// responses of non-conforming servers seen in the wild
+#[cfg(test)]
+const QUIRKY_STATUS_LINES: &[(&str, &str)] = &[
+  ("+OK POP3 server ready\n", "POP3 server ready"),
+  ("+ok POP3 server ready\r\n", "POP3 server ready"),
+  ("+Ok\n", ""),
+];
+
+#[cfg(test)]
+const QUIRKY_STATS: &[(&str, (u64, u64))] = &[
+  ("+OK 2 320\n", (2, 320)),
+  ("+ok 2 320\r\n", (2, 320)),
+  ("+OK  2  320\r\n", (2, 320)),
+  ("+OK\t2\t320\r\n", (2, 320)),
+];
+
+#[cfg(test)]
+const QUIRKY_LISTS: &[(&str, &[(u64, u64)])] = &[
+  ("+OK 2 messages\n1 120\n2 200\n.\n", &[(1, 120), (2, 200)]),
+  ("+OK\r\n1 120 \r\n2 200  \r\n.\r\n", &[(1, 120), (2, 200)]),
+  ("+OK\r\n1  120\r\n2\t200\r\n.\r\n", &[(1, 120), (2, 200)]),
+  ("+OK\r\n 1 120\r\n.\r\n", &[(1, 120)]),
+  ("+ok\r\n1 120\n2 200\r\n.\n", &[(1, 120), (2, 200)]),
+  ("+OK\n.\n", &[]),
+];
+
+#[cfg(test)]
+const QUIRKY_UIDLS: &[(&str, &[(u64, &str)])] = &[
+  ("+OK\n1 abc\n2 def\n.\n", &[(1, "abc"), (2, "def")]),
+  (
+    "+OK\r\n1  abc \r\n2\tdef\r\n.\r\n",
+    &[(1, "abc"), (2, "def")],
+  ),
+  (
+    "+OK\r\n1 0123456789012345678901234567890123456789012345678901234567890123456789xyz\r\n.\r\n",
+    &[(
+      1,
+      "0123456789012345678901234567890123456789012345678901234567890123456789xyz",
+    )],
+  ),
+];
+
+#[cfg(test)]
+const QUIRKY_RETRS: &[(&str, &str)] = &[
+  (
+    "+OK 12 octets\nSubject: a\n\nbody\n.\n",
+    "Subject: a\n\nbody\n",
+  ),
+  (
+    "+ok\r\nSubject: a\r\n\r\n..body\r\n.\r\n",
+    "Subject: a\r\n\r\n.body\r\n",
+  ),
+];
+
+/// decode a whole response at once, an incomplete one being an error
+#[cfg(test)]
+fn decode_whole(msg: &[u8], expect: Expect, mode: ParseMode) -> Result<Response> {
+  let mut decoder = ResponseDecoder::new(mode);
+  decoder.expect(expect);
+  decoder
+    .decode(&mut BytesMut::from(msg))?
+    .ok_or_else(|| anyhow! {"incomplete response"})
+}
+
+#[test]
+fn test_lenient_corpus() {
+  let decode = |msg: &str, expect, mode| decode_whole(msg.as_bytes(), expect, mode);
+  for (msg, expected) in QUIRKY_STATUS_LINES {
+    let res = decode(msg, Expect::Status, ParseMode::Lenient).and_then(Response::into_status);
+    assert_eq!(res.unwrap(), *expected, "{:?}", msg);
+  }
+  for (msg, expected) in QUIRKY_STATS {
+    assert!(decode(msg, Expect::Stat, ParseMode::Strict)
+      .and_then(Response::into_stat)
+      .is_err());
+    let (num, size, _) = decode(msg, Expect::Stat, ParseMode::Lenient)
+      .and_then(Response::into_stat)
+      .unwrap();
+    assert_eq!((num, size), *expected, "{:?}", msg);
+  }
+  for (msg, expected) in QUIRKY_LISTS {
+    let (list, _) = decode(msg, Expect::ScanListings, ParseMode::Lenient)
+      .and_then(Response::into_list)
+      .unwrap();
+    assert_eq!(list, *expected, "{:?}", msg);
+  }
+  for (msg, expected) in QUIRKY_UIDLS {
+    assert!(decode(msg, Expect::UniqueIdListings, ParseMode::Strict)
+      .and_then(Response::into_uidl)
+      .is_err());
+    let (list, _) = decode(msg, Expect::UniqueIdListings, ParseMode::Lenient)
+      .and_then(Response::into_uidl)
+      .unwrap();
+    let expected: Vec<_> = expected
+      .iter()
+      .map(|(id, unique_id)| (*id, unique_id.to_string()))
+      .collect();
+    assert_eq!(list, expected, "{:?}", msg);
+  }
+  for (msg, expected) in QUIRKY_RETRS {
+    assert!(decode(msg, Expect::Mail, ParseMode::Strict)
+      .and_then(Response::into_mail)
+      .is_err());
+    let (mail, _) = decode(msg, Expect::Mail, ParseMode::Lenient)
+      .and_then(Response::into_mail)
+      .unwrap();
+    assert_eq!(mail, expected.as_bytes(), "{:?}", msg);
+  }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+  #[test]
+  fn prop_decode_never_panics(msg: Vec<u8>) {
+    for mode in [ParseMode::Strict, ParseMode::Lenient] {
+      for expect in [
+        Expect::Status,
+        Expect::Stat,
+        Expect::ScanListing,
+        Expect::ScanListings,
+        Expect::UniqueIdListing,
+        Expect::UniqueIdListings,
+        Expect::Mail,
+      ] {
+        let _ = decode_whole(&msg, expect, mode);
+      }
+    }
+  }
+
+  #[test]
+  fn prop_decoder_matches_parse(
+    lines in proptest::collection::vec("(\\.{0,2}[ -~]{0,8}|)", 0..8),
+    chunk_len in 1usize..16,
+  ) {
+    let mut msg = b"+OK\r\n".to_vec();
+    for line in lines.iter() {
+      // a lone dot would end the response early
+      msg.extend_from_slice(if line == "." { ".." } else { line }.as_bytes());
+      msg.extend_from_slice(b"\r\n");
+    }
+    msg.extend_from_slice(b".\r\n");
+
+    let mut decoder = ResponseDecoder::new(ParseMode::Strict);
+    decoder.expect(Expect::Mail);
+    let mut buf = BytesMut::new();
+    let mut response = None;
+    for chunk in msg.chunks(chunk_len) {
+      proptest::prop_assert!(response.is_none());
+      buf.extend_from_slice(chunk);
+      response = decoder.decode(&mut buf).unwrap();
+    }
+
+    let (_, mail) = get_retr_result(ParseMode::Strict)(&msg).unwrap();
+    let (mail, _) = mail.unwrap();
+    match response {
+      Some(Response::Mail(decoded, _)) => proptest::prop_assert_eq!(&decoded[..], &mail[..]),
+      response => proptest::prop_assert!(false, "{:?}", response),
+    }
+  }
+
+  #[test]
+  fn prop_parse_stat_numbers(
+    num in "[0-9]{1,40}",
+    size in "[0-9]{1,40}",
+    tail in "[ -~]{0,20}",
+  ) {
+    let res = decode_whole(
+      format!("+OK {} {}{}\r\n", num, size, tail).as_bytes(),
+      Expect::Stat,
+      ParseMode::Strict,
+    )
+    .and_then(Response::into_stat);
+    match (num.parse::<u64>(), size.parse::<u64>()) {
+      (Ok(num), Ok(size)) if !tail.starts_with(|c: char| c.is_ascii_digit()) => {
+        let (n, s, _) = res.unwrap();
+        proptest::prop_assert_eq!((n, s), (num, size));
+      }
+      (Ok(_), Ok(_)) => {}
+      _ => proptest::prop_assert!(res.is_err()),
+    }
+  }
+
+  #[test]
+  fn prop_parse_list_numbers(listings in proptest::collection::vec("[0-9]{1,30} [0-9]{1,30}", 0..8)) {
+    let mut msg = b"+OK\r\n".to_vec();
+    for listing in listings.iter() {
+      msg.extend_from_slice(listing.as_bytes());
+      msg.extend_from_slice(b"\r\n");
+    }
+    msg.extend_from_slice(b".\r\n");
+    let expected = listings
+      .iter()
+      .map(|listing| {
+        let (id, size) = listing.split_once(' ').unwrap();
+        Ok((id.parse::<u64>()?, size.parse::<u64>()?))
+      })
+      .collect::<std::result::Result<Vec<_>, std::num::ParseIntError>>();
+    let res = decode_whole(&msg, Expect::ScanListings, ParseMode::Strict)
+      .and_then(Response::into_list);
+    match (res, expected) {
+      (Ok((list, _)), Ok(expected)) => proptest::prop_assert_eq!(list, expected),
+      (Err(_), Err(_)) => {}
+      (res, expected) => proptest::prop_assert!(false, "{:?} vs {:?}", res, expected),
+    }
+  }
+}
@@ -0,0 +1,426 @@
+//! proxies the connection to the server can go through, set up before TLS
+//!
+//! the host name of the server is sent to the proxy as is, so that it is resolved on the far side,
+//! as the local network may not resolve external names at all
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use crate::addr::percent_decode;
+
+/// maximum length of the response head of an HTTP proxy
+const MAX_HTTP_HEAD: usize = 8 * 1024;
+
+/// protocol spoken with a proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+  /// SOCKS5 (RFC 1928), with username and password authentication (RFC 1929)
+  Socks5,
+  /// HTTP `CONNECT` tunnel, with basic authentication
+  Http,
+}
+
+/// user name and password to authenticate to a proxy
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProxyCredentials {
+  /// user name
+  pub username: String,
+  /// password
+  pub password: String,
+}
+
+impl fmt::Debug for ProxyCredentials {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ProxyCredentials")
+      .field("username", &self.username)
+      .finish_non_exhaustive()
+  }
+}
+
+/// a proxy, written as `socks5://[user:password@]host:port` or `http://[user:password@]host:port`
+///
+/// reserved characters of the user name and password are percent-encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+  /// protocol spoken with the proxy
+  pub kind: ProxyKind,
+  /// address of the proxy, as `host:port`
+  pub addr: String,
+  /// credentials, if the proxy asks for them
+  pub credentials: Option<ProxyCredentials>,
+}
+
+impl FromStr for Proxy {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let invalid = || anyhow! {"invalid proxy {}, expected socks5:// or http:// with host:port", s};
+    let (scheme, rest) = s.split_once("://").ok_or_else(invalid)?;
+    let kind = match scheme.to_ascii_lowercase().as_str() {
+      "socks5" | "socks5h" => ProxyKind::Socks5,
+      "http" => ProxyKind::Http,
+      _ => return Err(invalid()),
+    };
+    let rest = rest.strip_suffix('/').unwrap_or(rest);
+    let (credentials, addr) = match rest.rsplit_once('@') {
+      Some((userinfo, addr)) => {
+        let (username, password) = userinfo.split_once(':').unwrap_or((userinfo, ""));
+        let credentials = ProxyCredentials {
+          username: percent_decode(username)?,
+          password: percent_decode(password)?,
+        };
+        (Some(credentials), addr)
+      }
+      None => (None, rest),
+    };
+    // the port is required, as proxies have no well-known one
+    match addr.rsplit_once(':') {
+      Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+      _ => return Err(invalid()),
+    }
+    Ok(Self {
+      kind,
+      addr: addr.to_string(),
+      credentials,
+    })
+  }
+}
+
+impl Proxy {
+  /// methods offered in the SOCKS5 greeting
+  fn socks5_greeting(&self) -> Vec<u8> {
+    match self.credentials {
+      // no authentication, username and password
+      Some(_) => vec![5, 2, 0, 2],
+      None => vec![5, 1, 0],
+    }
+  }
+
+  /// the authentication request for the method chosen by the SOCKS5 proxy, if any
+  fn socks5_auth(&self, reply: [u8; 2]) -> Result<Option<Vec<u8>>> {
+    match (reply, &self.credentials) {
+      ([5, 0], _) => Ok(None),
+      ([5, 2], Some(credentials)) => {
+        let (username, password) = (
+          credentials.username.as_bytes(),
+          credentials.password.as_bytes(),
+        );
+        if username.len() > 255 || password.len() > 255 {
+          return Err(anyhow! {"proxy user name or password longer than 255 octets"});
+        }
+        let mut request = vec![1, username.len() as u8];
+        request.extend_from_slice(username);
+        request.push(password.len() as u8);
+        request.extend_from_slice(password);
+        Ok(Some(request))
+      }
+      ([5, 0xff], _) | ([5, 2], None) => Err(anyhow! {"proxy accepts no offered authentication"}),
+      _ => Err(anyhow! {"invalid SOCKS5 reply from proxy"}),
+    }
+  }
+
+  /// the SOCKS5 `CONNECT` request to `host:port`, leaving a host name to the proxy to resolve
+  fn socks5_connect(host: &str, port: u16) -> Result<Vec<u8>> {
+    let mut request = vec![5, 1, 0];
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+      Ok(IpAddr::V4(ip)) => {
+        request.push(1);
+        request.extend_from_slice(&ip.octets());
+      }
+      Ok(IpAddr::V6(ip)) => {
+        request.push(4);
+        request.extend_from_slice(&ip.octets());
+      }
+      Err(_) => {
+        if host.len() > 255 {
+          return Err(anyhow! {"host name longer than 255 octets"});
+        }
+        request.push(3);
+        request.push(host.len() as u8);
+        request.extend_from_slice(host.as_bytes());
+      }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+  }
+
+  /// the number of octets left in the SOCKS5 `CONNECT` reply after its first 5
+  fn socks5_reply_rest(head: [u8; 5]) -> Result<usize> {
+    if head[0] != 5 {
+      return Err(anyhow! {"invalid SOCKS5 reply from proxy"});
+    }
+    let reason = match head[1] {
+      0 => None,
+      1 => Some("general failure"),
+      2 => Some("connection not allowed by ruleset"),
+      3 => Some("network unreachable"),
+      4 => Some("host unreachable"),
+      5 => Some("connection refused"),
+      6 => Some("TTL expired"),
+      7 => Some("command not supported"),
+      8 => Some("address type not supported"),
+      _ => Some("unknown error"),
+    };
+    if let Some(reason) = reason {
+      return Err(anyhow! {"proxy cannot connect to server: {}", reason});
+    }
+    // the bound address, of which one octet is already read, and the port
+    match head[3] {
+      1 => Ok(4 - 1 + 2),
+      4 => Ok(16 - 1 + 2),
+      3 => Ok(head[4] as usize + 2),
+      _ => Err(anyhow! {"invalid SOCKS5 reply from proxy"}),
+    }
+  }
+
+  /// the HTTP `CONNECT` request to `host:port`
+  fn http_connect(&self, host: &str, port: u16) -> Vec<u8> {
+    let authority = if host.contains(':') && !host.starts_with('[') {
+      format!("[{}]:{}", host, port)
+    } else {
+      format!("{}:{}", host, port)
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some(credentials) = &self.credentials {
+      let token = STANDARD.encode(format!("{}:{}", credentials.username, credentials.password));
+      request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    request.into_bytes()
+  }
+
+  /// check the status line of the response head of an HTTP proxy
+  fn http_status(head: &[u8]) -> Result<()> {
+    let head = String::from_utf8_lossy(head);
+    let status = head.lines().next().unwrap_or_default();
+    let mut parts = status.splitn(3, ' ');
+    match (parts.next(), parts.next()) {
+      (Some(version), Some(code)) if version.starts_with("HTTP/1.") => {
+        if code.starts_with('2') {
+          Ok(())
+        } else {
+          Err(anyhow! {"proxy cannot connect to server: {}", status})
+        }
+      }
+      _ => Err(anyhow! {"invalid HTTP reply from proxy"}),
+    }
+  }
+}
+
+/// connect to `host:port` through `proxy`
+pub(crate) async fn connect(proxy: &Proxy, host: &str, port: u16) -> Result<tokio::net::TcpStream> {
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  let mut stream = tokio::net::TcpStream::connect(proxy.addr.as_str())
+    .await
+    .map_err(|err| anyhow! {"cannot connect to proxy {}: {}", proxy.addr, err})?;
+  match proxy.kind {
+    ProxyKind::Socks5 => {
+      stream.write_all(&proxy.socks5_greeting()).await?;
+      let mut reply = [0u8; 2];
+      stream.read_exact(&mut reply).await?;
+      if let Some(request) = proxy.socks5_auth(reply)? {
+        stream.write_all(&request).await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+          return Err(anyhow! {"proxy rejected the user name or password"});
+        }
+      }
+      stream
+        .write_all(&Proxy::socks5_connect(host, port)?)
+        .await?;
+      let mut head = [0u8; 5];
+      stream.read_exact(&mut head).await?;
+      let mut rest = vec![0u8; Proxy::socks5_reply_rest(head)?];
+      stream.read_exact(&mut rest).await?;
+    }
+    ProxyKind::Http => {
+      stream.write_all(&proxy.http_connect(host, port)).await?;
+      // one octet at a time, not to read past the head into the session
+      let mut head = vec![];
+      while !head.ends_with(b"\r\n\r\n") {
+        if head.len() == MAX_HTTP_HEAD {
+          return Err(anyhow! {"HTTP reply from proxy too long"});
+        }
+        head.push(stream.read_u8().await?);
+      }
+      Proxy::http_status(&head)?;
+    }
+  }
+  Ok(stream)
+}
+
+/// like [`connect`], blocking
+#[cfg(feature = "blocking")]
+pub(crate) fn connect_blocking(
+  proxy: &Proxy,
+  host: &str,
+  port: u16,
+) -> Result<std::net::TcpStream> {
+  use std::io::{Read, Write};
+
+  let mut stream = std::net::TcpStream::connect(proxy.addr.as_str())
+    .map_err(|err| anyhow! {"cannot connect to proxy {}: {}", proxy.addr, err})?;
+  match proxy.kind {
+    ProxyKind::Socks5 => {
+      stream.write_all(&proxy.socks5_greeting())?;
+      let mut reply = [0u8; 2];
+      stream.read_exact(&mut reply)?;
+      if let Some(request) = proxy.socks5_auth(reply)? {
+        stream.write_all(&request)?;
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+          return Err(anyhow! {"proxy rejected the user name or password"});
+        }
+      }
+      stream.write_all(&Proxy::socks5_connect(host, port)?)?;
+      let mut head = [0u8; 5];
+      stream.read_exact(&mut head)?;
+      let mut rest = vec![0u8; Proxy::socks5_reply_rest(head)?];
+      stream.read_exact(&mut rest)?;
+    }
+    ProxyKind::Http => {
+      stream.write_all(&proxy.http_connect(host, port))?;
+      // one octet at a time, not to read past the head into the session
+      let mut head = vec![];
+      let mut byte = [0u8];
+      while !head.ends_with(b"\r\n\r\n") {
+        if head.len() == MAX_HTTP_HEAD {
+          return Err(anyhow! {"HTTP reply from proxy too long"});
+        }
+        stream.read_exact(&mut byte)?;
+        head.push(byte[0]);
+      }
+      Proxy::http_status(&head)?;
+    }
+  }
+  Ok(stream)
+}
+
+/// a proxy of `kind` on a local port, asking for `alice:secret`, relaying one connection to
+/// `target` whatever the requested destination, which the server returns
+#[cfg(test)]
+pub(crate) fn serve_proxy(
+  kind: ProxyKind,
+  target: String,
+) -> std::io::Result<(Proxy, std::thread::JoinHandle<Result<String>>)> {
+  use std::io::{Read, Write};
+
+  let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+  let proxy = Proxy {
+    kind,
+    addr: listener.local_addr()?.to_string(),
+    credentials: Some(ProxyCredentials {
+      username: "alice".to_string(),
+      password: "secret".to_string(),
+    }),
+  };
+  let server = std::thread::spawn(move || {
+    let (mut client, _) = listener.accept()?;
+    let destination = match kind {
+      ProxyKind::Socks5 => {
+        let mut greeting = [0u8; 4];
+        client.read_exact(&mut greeting)?;
+        assert_eq!(greeting, [5, 2, 0, 2]);
+        client.write_all(&[5, 2])?;
+        let mut auth = [0u8; 1 + 1 + 5 + 1 + 6];
+        client.read_exact(&mut auth)?;
+        assert_eq!(&auth, b"\x01\x05alice\x06secret");
+        client.write_all(&[1, 0])?;
+        let mut request = [0u8; 5];
+        client.read_exact(&mut request)?;
+        // a host name, to be resolved by the proxy
+        assert_eq!(request[..4], [5, 1, 0, 3]);
+        let mut host = vec![0u8; request[4] as usize];
+        client.read_exact(&mut host)?;
+        let mut port = [0u8; 2];
+        client.read_exact(&mut port)?;
+        client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])?;
+        format!("{}:{}", String::from_utf8(host)?, u16::from_be_bytes(port))
+      }
+      ProxyKind::Http => {
+        let mut head = vec![];
+        let mut byte = [0u8];
+        while !head.ends_with(b"\r\n\r\n") {
+          client.read_exact(&mut byte)?;
+          head.push(byte[0]);
+        }
+        let head = String::from_utf8(head)?;
+        assert!(head.contains("\r\nProxy-Authorization: Basic YWxpY2U6c2VjcmV0\r\n"));
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+        let request = head.lines().next().unwrap_or_default();
+        request
+          .strip_prefix("CONNECT ")
+          .and_then(|request| request.strip_suffix(" HTTP/1.1"))
+          .ok_or_else(|| anyhow! {"invalid request {}", request})?
+          .to_string()
+      }
+    };
+    let mut upstream = std::net::TcpStream::connect(target)?;
+    let (mut client_read, mut upstream_write) = (client.try_clone()?, upstream.try_clone()?);
+    let relay = std::thread::spawn(move || {
+      let _ = std::io::copy(&mut client_read, &mut upstream_write);
+      let _ = upstream_write.shutdown(std::net::Shutdown::Write);
+    });
+    let _ = std::io::copy(&mut upstream, &mut client);
+    let _ = client.shutdown(std::net::Shutdown::Both);
+    relay.join().unwrap();
+    Ok(destination)
+  });
+  Ok((proxy, server))
+}
+
+#[test]
+fn test_proxy_from_str() {
+  assert_eq!(
+    "socks5://proxy.example.com:1080".parse::<Proxy>().unwrap(),
+    Proxy {
+      kind: ProxyKind::Socks5,
+      addr: "proxy.example.com:1080".to_string(),
+      credentials: None,
+    }
+  );
+  let proxy = "http://alice:p%40ss%3Aword@[::1]:3128/"
+    .parse::<Proxy>()
+    .unwrap();
+  assert_eq!(proxy.kind, ProxyKind::Http);
+  assert_eq!(proxy.addr, "[::1]:3128");
+  assert_eq!(
+    proxy.credentials,
+    Some(ProxyCredentials {
+      username: "alice".to_string(),
+      password: "p@ss:word".to_string(),
+    })
+  );
+  assert!("socks4://proxy.example.com:1080".parse::<Proxy>().is_err());
+  assert!("http://proxy.example.com".parse::<Proxy>().is_err());
+  assert!("http://a%zz@proxy.example.com:3128"
+    .parse::<Proxy>()
+    .is_err());
+}
+
+#[test]
+fn test_proxy_requests() {
+  assert_eq!(
+    Proxy::socks5_connect("pop.example.com", 995).unwrap(),
+    b"\x05\x01\x00\x03\x0fpop.example.com\x03\xe3"
+  );
+  assert_eq!(Proxy::socks5_connect("::1", 110).unwrap()[3..5], [4, 0]);
+  assert_eq!(Proxy::socks5_reply_rest([5, 0, 0, 3, 9]).unwrap(), 11);
+  assert_eq!(
+    Proxy::socks5_reply_rest([5, 5, 0, 1, 0])
+      .unwrap_err()
+      .to_string(),
+    "proxy cannot connect to server: connection refused"
+  );
+
+  let proxy: Proxy = "http://proxy.example.com:3128".parse().unwrap();
+  assert_eq!(
+    proxy.http_connect("::1", 995),
+    b"CONNECT [::1]:995 HTTP/1.1\r\nHost: [::1]:995\r\n\r\n"
+  );
+  assert!(Proxy::http_status(b"HTTP/1.1 200 OK\r\n\r\n").is_ok());
+  assert!(Proxy::http_status(b"HTTP/1.0 407 Proxy Authentication Required\r\n\r\n").is_err());
+  assert!(Proxy::http_status(b"+OK ready\r\n\r\n").is_err());
+}
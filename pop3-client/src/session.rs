@@ -0,0 +1,308 @@
+//! the protocol state machine shared by [`crate::Pop3`] and [`crate::blocking::Pop3`], generic
+//! over how bytes reach the server
+//!
+//! a session is written once as `async` code over a [`Transport`]. The blocking transport does
+//! its I/O in place and never returns `Pending`, so that the blocking client runs the same futures
+//! to completion with [`block_on`] in a single poll.
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use std::{io, net::SocketAddr};
+use tokio_util::codec::Decoder;
+
+use crate::{
+  msg::Msg,
+  parser::{Expect, Response, ResponseDecoder},
+  tls::{HandshakeFailed, Verify},
+  ConnectOptions, ConnectionInfo, ParseMode, Security, ServerAddress, TlsConfig, TlsSession,
+  UntrustedCertificate,
+};
+
+/// minimum free space in the receive buffer before reading from the socket
+const READ_CHUNK: usize = 8 * 1024;
+
+/// how bytes reach the server, under a [`Session`]
+pub(crate) trait Transport: Sized {
+  /// connect to `domain:port` as set by `options`, directly, through a proxy or through a tunnel
+  /// command, returning the address connected to if there is one
+  async fn open(
+    domain: &str,
+    port: u16,
+    options: &ConnectOptions,
+  ) -> Result<(Self, Option<SocketAddr>)>;
+
+  /// wrap the connection into a TLS session with `domain`, returning what was negotiated
+  async fn start_tls(
+    self,
+    domain: &str,
+    config: &TlsConfig,
+    verify: Verify,
+  ) -> Result<(Self, TlsSession)>;
+
+  /// read at least one octet into the spare capacity of `buf`, returning 0 at the end of stream
+  async fn read_buf(&mut self, buf: &mut BytesMut) -> io::Result<usize>;
+
+  /// write and flush the whole of `buf`
+  async fn write_all(&mut self, buf: &[u8]) -> io::Result<()>;
+}
+
+/// a session with a POP3 server over `T`
+pub(crate) struct Session<T> {
+  socket: T,
+  buf: BytesMut,
+  decoder: ResponseDecoder,
+  msg: Msg,
+  info: ConnectionInfo,
+}
+
+impl<T: Transport> Session<T> {
+  /// see [`crate::Pop3::connect`]
+  pub(crate) async fn connect(addr: &str, options: &ConnectOptions) -> Result<(Self, String)> {
+    match Self::open(addr, options, Verify::Config).await {
+      Err(err) if err.is::<HandshakeFailed>() => {
+        // connect again accepting any certificate to tell whether it was the reason, and to show
+        // it to the user
+        match Self::open(addr, options, Verify::Probe).await {
+          Ok(session) => Err(
+            UntrustedCertificate {
+              chain: session.info.tls.map(|tls| tls.chain).unwrap_or_default(),
+              reason: err.to_string(),
+            }
+            .into(),
+          ),
+          Err(_) => Err(err),
+        }
+      }
+      result => result.map(|session| {
+        let greeting_msg = session.info.greeting.clone();
+        (session, greeting_msg)
+      }),
+    }
+  }
+
+  async fn open(addr: &str, options: &ConnectOptions, verify: Verify) -> Result<Self> {
+    let addr: ServerAddress = addr.parse()?;
+    let security = addr.security_or(options.security);
+    let (domain, port) = (addr.host.as_str(), addr.port_or_default(security));
+
+    if options.tunnel.is_some() && options.proxy.is_some() {
+      return Err(anyhow! {"a tunnel command cannot go through a proxy"});
+    }
+    let (socket, remote_addr) = T::open(domain, port, options).await?;
+    let (socket, tls) = match security {
+      Security::Tls => {
+        let (socket, session) = socket.start_tls(domain, &options.tls, verify).await?;
+        (socket, Some(session))
+      }
+      Security::Plain | Security::StartTls => (socket, None),
+    };
+    let mut session = Self {
+      socket,
+      buf: BytesMut::with_capacity(READ_CHUNK),
+      decoder: ResponseDecoder::new(options.mode),
+      msg: Default::default(),
+      info: ConnectionInfo {
+        remote_addr,
+        security,
+        greeting: String::new(),
+        tls,
+        preauthenticated: matches!(&options.tunnel, Some(tunnel) if tunnel.preauth),
+      },
+    };
+
+    session.info.greeting = session.read_response(Expect::Status).await?.into_status()?;
+
+    if security == Security::StartTls {
+      session.msg.stls()?;
+      session.command(Expect::Status).await?.into_status()?;
+      // anything already received could have been injected before the handshake
+      if !session.buf.is_empty() {
+        return Err(anyhow! {"unexpected data before TLS negotiation"});
+      }
+      let (socket, tls) = session
+        .socket
+        .start_tls(domain, &options.tls, verify)
+        .await?;
+      session.socket = socket;
+      session.info.tls = Some(tls);
+    }
+
+    Ok(session)
+  }
+
+  pub(crate) fn connection_info(&self) -> &ConnectionInfo {
+    &self.info
+  }
+
+  pub(crate) fn mode(&self) -> ParseMode {
+    self.decoder.mode()
+  }
+
+  pub(crate) fn set_mode(&mut self, mode: ParseMode) {
+    self.decoder.set_mode(mode);
+  }
+
+  async fn read_response(&mut self, expect: Expect) -> Result<Response> {
+    self.decoder.expect(expect);
+    loop {
+      if let Some(response) = self.decoder.decode(&mut self.buf)? {
+        return Ok(response);
+      }
+      self.buf.reserve(READ_CHUNK);
+      if self.socket.read_buf(&mut self.buf).await? == 0 {
+        return Err(anyhow! {"connection closed by remote"});
+      }
+    }
+  }
+
+  async fn command(&mut self, expect: Expect) -> Result<Response> {
+    self.socket.write_all(self.msg.get_msg()).await?;
+    self.read_response(expect).await
+  }
+
+  pub(crate) async fn user(&mut self, name: &str) -> Result<String> {
+    self.msg.user(name)?;
+    self.command(Expect::Status).await?.into_status()
+  }
+
+  pub(crate) async fn pass(&mut self, secret: &str) -> Result<String> {
+    self.msg.pass(secret)?;
+    self.command(Expect::Status).await?.into_status()
+  }
+
+  pub(crate) async fn auth_external(&mut self, authzid: Option<&str>) -> Result<String> {
+    self.msg.auth_external(authzid)?;
+    self.command(Expect::Status).await?.into_status()
+  }
+
+  pub(crate) async fn stat(&mut self) -> Result<(u64, u64, String)> {
+    self.msg.stat()?;
+    self.command(Expect::Stat).await?.into_stat()
+  }
+
+  pub(crate) async fn list(&mut self, id: Option<u64>) -> Result<(Vec<(u64, u64)>, String)> {
+    self.msg.list(id)?;
+    let expect = if id.is_some() {
+      Expect::ScanListing
+    } else {
+      Expect::ScanListings
+    };
+    self.command(expect).await?.into_list()
+  }
+
+  pub(crate) async fn retr(&mut self, id: u64) -> Result<(Bytes, String)> {
+    self.msg.retr(id)?;
+    self.command(Expect::Mail).await?.into_mail()
+  }
+
+  pub(crate) async fn top(&mut self, id: u64, lines: u64) -> Result<(Bytes, String)> {
+    self.msg.top(id, lines)?;
+    self.command(Expect::Mail).await?.into_mail()
+  }
+
+  pub(crate) async fn dele(&mut self, id: u64) -> Result<String> {
+    self.msg.dele(id)?;
+    self.command(Expect::Status).await?.into_status()
+  }
+
+  pub(crate) async fn uidl(&mut self, id: Option<u64>) -> Result<(Vec<(u64, String)>, String)> {
+    self.msg.uidl(id)?;
+    let expect = if id.is_some() {
+      Expect::UniqueIdListing
+    } else {
+      Expect::UniqueIdListings
+    };
+    self.command(expect).await?.into_uidl()
+  }
+
+  pub(crate) async fn quit(mut self) -> Result<String> {
+    self.msg.quit()?;
+    self.command(Expect::Status).await?.into_status()
+  }
+}
+
+/// the output of `future`, which must complete without waiting, as it does over the blocking
+/// transport
+#[cfg(feature = "blocking")]
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+  use std::task::{Context, Poll, Waker};
+
+  let mut future = std::pin::pin!(future);
+  match future
+    .as_mut()
+    .poll(&mut Context::from_waker(Waker::noop()))
+  {
+    Poll::Ready(output) => output,
+    Poll::Pending => unreachable!("blocking I/O never waits"),
+  }
+}
+
+/// the methods of a client over a [`Session`], documented once for both clients
+///
+/// `async` makes them `async` and `blocking` runs them with [`block_on`]
+macro_rules! client_methods {
+  ($kind:tt) => {
+    client_methods! {
+      @$kind
+      /// send `USER`, returning the status text
+      fn user(&mut self, name: &str) -> Result<String>;
+      /// send `PASS`, returning the status text
+      fn pass(&mut self, secret: &str) -> Result<String>;
+      /// log in with SASL `EXTERNAL`, relying on the client certificate of the TLS session,
+      /// returning the status text
+      ///
+      /// the server decides who is logged in from the certificate, unless `authzid` asks for
+      /// another user the certificate is allowed to act as
+      fn auth_external(&mut self, authzid: Option<&str>) -> Result<String>;
+      /// number of messages and size of the maildrop in octets, with the status text
+      fn stat(&mut self) -> Result<(u64, u64, String)>;
+      /// message numbers and sizes of one or all messages, with the status text
+      fn list(&mut self, id: Option<u64>) -> Result<(Vec<(u64, u64)>, String)>;
+      /// mail `id` with dot-stuffing removed, with the status text
+      fn retr(&mut self, id: u64) -> Result<(Bytes, String)>;
+      /// headers of mail `id` followed by its first `lines` body lines
+      fn top(&mut self, id: u64, lines: u64) -> Result<(Bytes, String)>;
+      /// mark mail `id` as deleted, which takes effect once the session ends with `quit`
+      fn dele(&mut self, id: u64) -> Result<String>;
+      /// message numbers and unique-ids of one or all messages, with the status text
+      fn uidl(&mut self, id: Option<u64>) -> Result<(Vec<(u64, String)>, String)>;
+    }
+
+    /// how the session was set up
+    pub fn connection_info(&self) -> &ConnectionInfo {
+      self.session.connection_info()
+    }
+
+    /// how strictly responses are checked
+    pub fn mode(&self) -> ParseMode {
+      self.session.mode()
+    }
+
+    /// change how strictly the following responses are checked
+    pub fn set_mode(&mut self, mode: ParseMode) {
+      self.session.set_mode(mode);
+    }
+  };
+  (@async $(
+    $(#[$attr:meta])* fn $name:ident(&mut self $(, $arg:ident: $ty:ty)*) -> $ret:ty;
+  )*) => {
+    $(
+      $(#[$attr])*
+      pub async fn $name(&mut self $(, $arg: $ty)*) -> $ret {
+        self.session.$name($($arg),*).await
+      }
+    )*
+  };
+  (@blocking $(
+    $(#[$attr:meta])* fn $name:ident(&mut self $(, $arg:ident: $ty:ty)*) -> $ret:ty;
+  )*) => {
+    $(
+      $(#[$attr])*
+      pub fn $name(&mut self $(, $arg: $ty)*) -> $ret {
+        crate::session::block_on(self.session.$name($($arg),*))
+      }
+    )*
+  };
+}
+
+pub(crate) use client_methods;
@@ -0,0 +1,377 @@
+//! scripted servers and TLS settings shared by the tests of both clients
+
+use crate::{tls, Certificate, Fingerprint, Security, TlsConfig};
+
+/// replies of a scripted server, starting with the greeting, each split into the chunks it is
+/// written in
+pub(crate) type Session = &'static [&'static [&'static [u8]]];
+
+pub(crate) const LENIENT_SESSION: Session = &[
+  &[b"+ok ready\n"],
+  &[b"+OK  2  320 \n"],
+  &[b"+OK\n1 120 \n2\t200\n.\n"],
+  &[b"+OK bye\n"],
+];
+
+pub(crate) const MULTILINE_SESSION: Session = &[
+  &[b"+OK\r\n"],
+  &[b"+OK\r\n.\r\n"],
+  &[b"-ERR no such message\r\n"],
+  // the mail in fragments, splitting the terminator across writes
+  &[b"+OK\r\nline\r", b"\n.", b".\r\n\r", b"\n.\r", b"\n"],
+  &[b"+OK\r\n"],
+];
+
+/// shell command of a server which starts logged in, telling the host and port it was run for on
+/// `QUIT`
+#[cfg(unix)]
+pub(crate) const PREAUTH_TUNNEL: &str = concat!(
+  r"printf '+OK logged in\r\n'; read cmd; ",
+  r"printf '+OK 2 320\r\n'; read cmd; ",
+  r"printf '+OK %h %p\r\n'",
+);
+
+pub(crate) const TLS_SESSION: Session =
+  &[&[b"+OK ready\r\n"], &[b"+OK 1 120\r\n"], &[b"+OK bye\r\n"]];
+
+pub(crate) const STLS_SESSION: Session = &[
+  &[b"+OK ready\r\n"],
+  &[b"+OK begin TLS negotiation\r\n"],
+  &[b"+OK 1 120\r\n"],
+  &[b"+OK bye\r\n"],
+];
+
+pub(crate) const STLS_INJECTED_SESSION: Session = &[
+  &[b"+OK ready\r\n"],
+  &[b"+OK begin TLS negotiation\r\n+OK 1 120\r\n"],
+];
+
+#[cfg(feature = "rustls")]
+pub(crate) const EXTERNAL_SESSION: Session = &[
+  &[b"+OK ready\r\n"],
+  &[b"+OK alice authenticated\r\n"],
+  &[b"+OK 1 120\r\n"],
+  &[b"+OK bye\r\n"],
+];
+
+/// TLS settings trusting the certificate `serve` presents as `localhost`
+pub(crate) fn test_tls_config() -> TlsConfig {
+  TlsConfig {
+    root_certificates: Certificate::from_pem(include_bytes!("../testdata/ca.pem")).unwrap(),
+    ..Default::default()
+  }
+}
+
+/// fingerprint of the certificate `serve` presents
+pub(crate) fn test_fingerprint() -> Fingerprint {
+  Certificate::from_pem(include_bytes!("../testdata/localhost.pem")).unwrap()[0].fingerprint()
+}
+
+/// write `replies`, reading one command before each, except before the greeting
+fn play(
+  socket: &mut (impl std::io::Read + std::io::Write),
+  replies: Session,
+  greeting: bool,
+) -> std::io::Result<()> {
+  let mut buf = [0u8; 64];
+  for (i, reply) in replies.iter().enumerate() {
+    if i > 0 || !greeting {
+      let _ = socket.read(&mut buf)?;
+    }
+    for chunk in reply.iter() {
+      socket.write_all(chunk)?;
+      if reply.len() > 1 {
+        std::thread::sleep(std::time::Duration::from_millis(5));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// serve `session` on a local port, secured with `security`, where `STLS` is the first command
+///
+/// a client failing the session, for instance by rejecting the certificate, is followed by the
+/// next one, so the server only ends once the session is played in full
+pub(crate) fn serve(
+  session: Session,
+  security: Security,
+) -> std::io::Result<(String, std::thread::JoinHandle<anyhow::Result<()>>)> {
+  let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+  let addr = listener.local_addr()?.to_string();
+  let server = std::thread::spawn(move || loop {
+    let (mut socket, _) = listener.accept()?;
+    socket.set_nodelay(true)?;
+    let played = match security {
+      Security::Plain => play(&mut socket, session, true).map_err(Into::into),
+      Security::Tls => tls::accept(socket).and_then(|mut socket| {
+        play(&mut socket, session, true)?;
+        Ok(())
+      }),
+      Security::StartTls => play(&mut socket, &session[..2], true)
+        .map_err(Into::into)
+        .and_then(|_| tls::accept(socket))
+        .and_then(|mut socket| {
+          play(&mut socket, &session[2..], false)?;
+          Ok(())
+        }),
+    };
+    if played.is_ok() {
+      return Ok(());
+    }
+  });
+  Ok((addr, server))
+}
+
+/// serve `session` on a local port over TLS, requiring a client certificate issued by the test CA
+#[cfg(feature = "rustls")]
+pub(crate) fn serve_authenticated(
+  session: Session,
+) -> std::io::Result<(String, std::thread::JoinHandle<anyhow::Result<()>>)> {
+  let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+  let addr = listener.local_addr()?.to_string();
+  let server = std::thread::spawn(move || {
+    let (socket, _) = listener.accept()?;
+    socket.set_nodelay(true)?;
+    let mut socket = tls::accept_authenticated(socket)?;
+    play(&mut socket, session, true)?;
+    Ok(())
+  });
+  Ok((addr, server))
+}
+
+/// the tests of a client, run by `Pop3` in scope, generated for both clients
+///
+/// `#[tokio::test] async` makes them `async`, awaiting each call, and `#[test]` runs them blocking
+macro_rules! client_tests {
+  (#[$test:meta] async) => {
+    crate::test_support::client_tests!(@ #[$test] (async) (.await));
+  };
+  (#[$test:meta]) => {
+    crate::test_support::client_tests!(@ #[$test] () ());
+  };
+  (@ #[$test:meta] ($($async:tt)*) ($($wait:tt)*)) => {
+    #[$test]
+    $($async)* fn test_pop3_lenient() -> anyhow::Result<()> {
+      let (addr, server) =
+        crate::test_support::serve(crate::test_support::LENIENT_SESSION, crate::Security::Plain)?;
+      let (mut pop3, welcome_msg) =
+        Pop3::with_mode(&addr, false, crate::ParseMode::Lenient)$($wait)*?;
+      assert_eq!(welcome_msg, "ready");
+      assert!(pop3.connection_info().tls.is_none());
+      assert_eq!(pop3.stat()$($wait)*?.0, 2);
+      assert_eq!(pop3.list(None)$($wait)*?.0, vec![(1, 120), (2, 200)]);
+      assert_eq!(pop3.quit()$($wait)*?, "bye");
+      server.join().unwrap()?;
+      Ok(())
+    }
+
+    #[$test]
+    $($async)* fn test_pop3_multiline_termination() -> anyhow::Result<()> {
+      let (addr, server) =
+        crate::test_support::serve(crate::test_support::MULTILINE_SESSION, crate::Security::Plain)?;
+      // the URL scheme overrides the default of TLS
+      let url = format!("pop3://alice@{}", addr);
+      let (mut pop3, _) = Pop3::connect(&url, &crate::ConnectOptions::default())$($wait)*?;
+      assert_eq!(pop3.list(None)$($wait)*?.0, vec![]);
+      assert_eq!(
+        pop3.retr(1)$($wait)*.unwrap_err().to_string(),
+        "no such message"
+      );
+      assert_eq!(pop3.retr(1)$($wait)*?.0, &b"line\r\n.\r\n\r\n"[..]);
+      pop3.quit()$($wait)*?;
+      server.join().unwrap()?;
+      Ok(())
+    }
+
+    #[$test]
+    $($async)* fn test_pop3_tls() -> anyhow::Result<()> {
+      for (session, security) in [
+        (crate::test_support::TLS_SESSION, crate::Security::Tls),
+        (crate::test_support::STLS_SESSION, crate::Security::StartTls),
+      ] {
+        let (addr, server) = crate::test_support::serve(session, security)?;
+        let options = crate::ConnectOptions {
+          security,
+          tls: crate::test_support::test_tls_config(),
+          ..Default::default()
+        };
+        let (mut pop3, welcome_msg) = Pop3::connect(&addr, &options)$($wait)*?;
+        assert_eq!(welcome_msg, "ready");
+        let info = pop3.connection_info();
+        assert_eq!(info.remote_addr.unwrap().to_string(), addr);
+        assert_eq!(info.security, security);
+        assert_eq!(info.greeting, "ready");
+        let tls = info.tls.as_ref().unwrap();
+        assert_eq!(
+          tls.chain[0].fingerprint(),
+          crate::test_support::test_fingerprint()
+        );
+        assert_eq!(tls.version.is_some(), cfg!(feature = "rustls"));
+        assert_eq!(pop3.list(Some(1))$($wait)*?.0, vec![(1, 120)]);
+        assert_eq!(pop3.quit()$($wait)*?, "bye");
+        server.join().unwrap()?;
+      }
+      Ok(())
+    }
+
+    #[cfg(unix)]
+    #[$test]
+    $($async)* fn test_pop3_tunnel() -> anyhow::Result<()> {
+      let mut options = crate::ConnectOptions {
+        security: crate::Security::Plain,
+        tunnel: Some(crate::Tunnel {
+          command: crate::test_support::PREAUTH_TUNNEL.to_string(),
+          preauth: true,
+        }),
+        ..Default::default()
+      };
+      let (mut pop3, welcome_msg) = Pop3::connect("pop.example.com", &options)$($wait)*?;
+      assert_eq!(welcome_msg, "logged in");
+      let info = pop3.connection_info();
+      assert!(info.preauthenticated);
+      assert!(info.remote_addr.is_none());
+      assert_eq!(pop3.stat()$($wait)*?.0, 2);
+      assert_eq!(pop3.quit()$($wait)*?, "pop.example.com 110");
+
+      // a command that exits without a greeting
+      options.tunnel = Some(crate::Tunnel::new("exit 0"));
+      assert!(Pop3::connect("pop.example.com", &options)$($wait)*.is_err());
+      Ok(())
+    }
+
+    #[cfg(feature = "rustls")]
+    #[$test]
+    $($async)* fn test_pop3_tls_client_identity() -> anyhow::Result<()> {
+      let (addr, server) =
+        crate::test_support::serve_authenticated(crate::test_support::EXTERNAL_SESSION)?;
+      let mut options = crate::ConnectOptions {
+        tls: crate::test_support::test_tls_config(),
+        ..Default::default()
+      };
+      options.tls.identity = Some(crate::Identity::from_pkcs12(
+        include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/client.p12")),
+        "secret",
+      )?);
+      let (mut pop3, _) = Pop3::connect(&addr, &options)$($wait)*?;
+      assert_eq!(pop3.auth_external(None)$($wait)*?, "alice authenticated");
+      assert_eq!(pop3.list(Some(1))$($wait)*?.0, vec![(1, 120)]);
+      assert_eq!(pop3.quit()$($wait)*?, "bye");
+      server.join().unwrap()?;
+      Ok(())
+    }
+
+    #[$test]
+    $($async)* fn test_pop3_proxy() -> anyhow::Result<()> {
+      for kind in [crate::ProxyKind::Socks5, crate::ProxyKind::Http] {
+        let (addr, server) =
+          crate::test_support::serve(crate::test_support::TLS_SESSION, crate::Security::Tls)?;
+        let (proxy, proxy_server) = crate::proxy::serve_proxy(kind, addr.clone())?;
+        // the host name is left to the proxy to resolve
+        let port = addr.rsplit_once(':').unwrap().1;
+        let options = crate::ConnectOptions {
+          tls: crate::test_support::test_tls_config(),
+          proxy: Some(proxy),
+          ..Default::default()
+        };
+        let (mut pop3, _) = Pop3::connect(&format!("localhost:{}", port), &options)$($wait)*?;
+        assert_eq!(pop3.list(Some(1))$($wait)*?.0, vec![(1, 120)]);
+        assert_eq!(pop3.quit()$($wait)*?, "bye");
+        server.join().unwrap()?;
+        assert_eq!(proxy_server.join().unwrap()?, format!("localhost:{}", port));
+      }
+      Ok(())
+    }
+
+    #[$test]
+    $($async)* fn test_pop3_tls_pinned() -> anyhow::Result<()> {
+      let (addr, server) =
+        crate::test_support::serve(crate::test_support::TLS_SESSION, crate::Security::Tls)?;
+      let mut options = crate::ConnectOptions::default();
+      options.tls.pinned_fingerprints = vec![crate::test_support::test_fingerprint()];
+      let (mut pop3, _) = Pop3::connect(&addr, &options)$($wait)*?;
+      assert_eq!(pop3.list(Some(1))$($wait)*?.0, vec![(1, 120)]);
+      assert_eq!(pop3.quit()$($wait)*?, "bye");
+      server.join().unwrap()?;
+      Ok(())
+    }
+
+    #[$test]
+    $($async)* fn test_pop3_tls_rejected() -> anyhow::Result<()> {
+      // the test certificate is not trusted by default, and reported for the user to pin it
+      let (addr, _) =
+        crate::test_support::serve(crate::test_support::TLS_SESSION, crate::Security::Tls)?;
+      let err = Pop3::connect(&addr, &crate::ConnectOptions::default())$($wait)*
+        .err()
+        .unwrap();
+      let untrusted = err.downcast::<crate::UntrustedCertificate>()?;
+      assert_eq!(
+        untrusted.chain[0].fingerprint(),
+        crate::test_support::test_fingerprint()
+      );
+
+      // nor when another certificate is pinned, even with the roots trusted
+      let (addr, _) =
+        crate::test_support::serve(crate::test_support::STLS_SESSION, crate::Security::StartTls)?;
+      let mut options = crate::ConnectOptions {
+        security: crate::Security::StartTls,
+        tls: crate::test_support::test_tls_config(),
+        ..Default::default()
+      };
+      options.tls.pinned_fingerprints = vec![Default::default()];
+      let err = Pop3::connect(&addr, &options)$($wait)*.err().unwrap();
+      assert!(err.is::<crate::UntrustedCertificate>());
+
+      // a reply smuggled in before the handshake
+      let (addr, _) = crate::test_support::serve(
+        crate::test_support::STLS_INJECTED_SESSION,
+        crate::Security::Plain,
+      )?;
+      let options = crate::ConnectOptions {
+        security: crate::Security::StartTls,
+        tls: crate::test_support::test_tls_config(),
+        ..Default::default()
+      };
+      assert_eq!(
+        Pop3::connect(&addr, &options)$($wait)*
+          .err()
+          .unwrap()
+          .to_string(),
+        "unexpected data before TLS negotiation"
+      );
+      Ok(())
+    }
+
+    #[$test]
+    #[ignore = "needs a real server, given by POP3_ADDR, POP3_USER and POP3_PASS"]
+    $($async)* fn test_pop3() -> anyhow::Result<()> {
+      use std::env::var;
+
+      let (mut pop3, welcome_msg) = Pop3::new(&var("POP3_ADDR").unwrap(), false)$($wait)*?;
+      println!("{}", welcome_msg);
+      pop3.user(&var("POP3_USER").unwrap())$($wait)*?;
+      pop3.pass(&var("POP3_PASS").unwrap())$($wait)*?;
+
+      let (msg_num, maildrop_bytes, additional_msg) = pop3.stat()$($wait)*?;
+      println!("{} {} \"{}\"", msg_num, maildrop_bytes, additional_msg);
+
+      let (list, msg) = pop3.list(None)$($wait)*?;
+      println!("{:?}", msg);
+      println!("{:#?}", list);
+
+      if let Some((id, _)) = list.first().cloned() {
+        println!("{:?}", pop3.list(Some(id))$($wait)*);
+        let (mail, msg) = pop3.retr(id)$($wait)*?;
+        println!("+OK {}", msg);
+        println!("{:?}", String::from_utf8_lossy(&mail));
+      }
+      // println!("{:?}", pop3.list(Some(0))$($wait)*);
+      // println!("{:?}", pop3.list(Some(std::u64::MAX))$($wait)*);
+
+      let goodbye_message = pop3.quit()$($wait)*?;
+      println!("{}", goodbye_message);
+      Ok(())
+    }
+  };
+}
+
+pub(crate) use client_tests;
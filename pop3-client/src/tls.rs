@@ -0,0 +1,367 @@
+//! TLS settings shared by implicit TLS and `STLS`, applied with the backend selected by the
+//! `native-tls` or `rustls` feature, the latter taking precedence when both are enabled
+
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("pop3-client needs a TLS backend, enable the `native-tls` or `rustls` feature");
+
+#[cfg_attr(feature = "rustls", path = "tls/rustls.rs")]
+#[cfg_attr(not(feature = "rustls"), path = "tls/native_tls.rs")]
+mod backend;
+
+#[cfg(test)]
+pub(crate) use backend::accept;
+#[cfg(all(test, feature = "rustls"))]
+pub(crate) use backend::accept_authenticated;
+pub(crate) use backend::connect;
+#[cfg(feature = "blocking")]
+pub(crate) use backend::connect_blocking;
+
+/// an X.509 certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate(Vec<u8>);
+
+impl Certificate {
+  /// a certificate in DER
+  pub fn from_der(der: Vec<u8>) -> Self {
+    Self(der)
+  }
+
+  /// every certificate of a PEM bundle
+  pub fn from_pem(pem: &[u8]) -> Result<Vec<Self>> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])?;
+    if certs.is_empty() {
+      return Err(anyhow! {"no certificate found in PEM"});
+    }
+    Ok(certs.into_iter().map(Self).collect())
+  }
+
+  /// the certificate in DER
+  pub fn der(&self) -> &[u8] {
+    &self.0
+  }
+
+  /// SHA-256 digest of the certificate
+  pub fn fingerprint(&self) -> Fingerprint {
+    Fingerprint(Sha256::digest(&self.0).into())
+  }
+
+  /// the fields of the certificate a user needs to decide whether to trust it
+  pub fn info(&self) -> Result<CertificateInfo> {
+    use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+    let (_, cert) =
+      X509Certificate::from_der(&self.0).map_err(|err| anyhow! {"invalid certificate: {}", err})?;
+    let subject_alt_names = match cert.subject_alternative_name() {
+      Ok(Some(names)) => names
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+          GeneralName::DNSName(name) | GeneralName::RFC822Name(name) => Some(name.to_string()),
+          GeneralName::IPAddress(&[a, b, c, d]) => Some(IpAddr::from([a, b, c, d]).to_string()),
+          GeneralName::IPAddress(addr) => <[u8; 16]>::try_from(*addr)
+            .ok()
+            .map(|addr| IpAddr::from(addr).to_string()),
+          _ => None,
+        })
+        .collect(),
+      _ => vec![],
+    };
+    Ok(CertificateInfo {
+      subject: cert.subject().to_string(),
+      issuer: cert.issuer().to_string(),
+      subject_alt_names,
+      not_before: cert.validity().not_before.timestamp(),
+      not_after: cert.validity().not_after.timestamp(),
+      fingerprint: self.fingerprint(),
+    })
+  }
+}
+
+/// SHA-256 digest of a certificate, written as colon separated hex pairs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(into = "String", try_from = "String")
+)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl fmt::Display for Fingerprint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, byte) in self.0.iter().enumerate() {
+      if i > 0 {
+        write!(f, ":")?;
+      }
+      write!(f, "{:02X}", byte)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for Fingerprint {
+  type Err = anyhow::Error;
+
+  /// hex digits, optionally separated by colons or spaces
+  fn from_str(s: &str) -> Result<Self> {
+    let digits = s
+      .chars()
+      .filter(|c| !matches!(c, ':' | ' '))
+      .map(|c| c.to_digit(16).map(|digit| digit as u8))
+      .collect::<Option<Vec<_>>>()
+      .filter(|digits| digits.len() == 64)
+      .ok_or_else(|| anyhow! {"invalid SHA-256 fingerprint: {}", s})?;
+    let mut fingerprint = [0u8; 32];
+    for (byte, pair) in fingerprint.iter_mut().zip(digits.chunks(2)) {
+      *byte = pair[0] << 4 | pair[1];
+    }
+    Ok(Self(fingerprint))
+  }
+}
+
+impl From<Fingerprint> for String {
+  fn from(fingerprint: Fingerprint) -> Self {
+    fingerprint.to_string()
+  }
+}
+
+impl TryFrom<String> for Fingerprint {
+  type Error = anyhow::Error;
+
+  fn try_from(s: String) -> Result<Self> {
+    s.parse()
+  }
+}
+
+/// readable fields of a certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CertificateInfo {
+  /// distinguished name of the owner
+  pub subject: String,
+  /// distinguished name of the signer, the same as `subject` when self-signed
+  pub issuer: String,
+  /// host names, e-mail and IP addresses the certificate is valid for
+  pub subject_alt_names: Vec<String>,
+  /// start of the validity period, in seconds since the Unix epoch
+  pub not_before: i64,
+  /// end of the validity period, in seconds since the Unix epoch
+  pub not_after: i64,
+  /// SHA-256 digest of the certificate
+  pub fingerprint: Fingerprint,
+}
+
+/// the server certificate could not be verified, or matches none of the pinned fingerprints
+///
+/// `chain` holds the certificates the server presented, leaf first, so that the user can decide to
+/// trust the leaf by pinning its fingerprint. With `native-tls` only the leaf is known.
+#[derive(Debug, Clone)]
+pub struct UntrustedCertificate {
+  /// certificates presented by the server, leaf first
+  pub chain: Vec<Certificate>,
+  /// why the certificate was rejected
+  pub reason: String,
+}
+
+impl fmt::Display for UntrustedCertificate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "untrusted server certificate: {}", self.reason)
+  }
+}
+
+impl std::error::Error for UntrustedCertificate {}
+
+/// a failed TLS handshake, which may be due to the server certificate
+#[derive(Debug)]
+pub(crate) struct HandshakeFailed(pub(crate) anyhow::Error);
+
+impl fmt::Display for HandshakeFailed {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.0.fmt(f)
+  }
+}
+
+impl std::error::Error for HandshakeFailed {}
+
+/// how the server certificate is checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verify {
+  /// as set by [`TlsConfig`]
+  Config,
+  /// accept any certificate, only to find out which one the server presents
+  Probe,
+}
+
+/// a client certificate chain with its private key, presented when the server asks for one
+#[derive(Clone)]
+pub struct Identity {
+  /// leaf first
+  chain: Vec<Certificate>,
+  /// PKCS#8 in DER
+  key: Vec<u8>,
+}
+
+impl Identity {
+  /// an identity from a PEM certificate chain, leaf first, and a PEM PKCS#8 private key
+  pub fn from_pem(chain: &[u8], key: &[u8]) -> Result<Self> {
+    Ok(Self {
+      chain: Certificate::from_pem(chain)?,
+      key: rustls_pemfile::pkcs8_private_keys(&mut &key[..])?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow! {"no PKCS#8 private key found in PEM"})?,
+    })
+  }
+
+  /// an identity from a PKCS#12 archive encrypted with `password`, taking its first private key
+  pub fn from_pkcs12(der: &[u8], password: &str) -> Result<Self> {
+    let keystore = p12_keystore::KeyStore::from_pkcs12(der, password)
+      .map_err(|err| anyhow! {"invalid PKCS#12 archive: {}", err})?;
+    let (_, key_chain) = keystore
+      .private_key_chain()
+      .ok_or_else(|| anyhow! {"no private key found in PKCS#12 archive"})?;
+    if key_chain.chain().is_empty() {
+      return Err(anyhow! {"no certificate found for the private key in PKCS#12 archive"});
+    }
+    Ok(Self {
+      chain: key_chain
+        .chain()
+        .iter()
+        .map(|cert| Certificate::from_der(cert.as_der().to_vec()))
+        .collect(),
+      key: key_chain.key().to_vec(),
+    })
+  }
+
+  /// the certificate presented to the server
+  pub fn certificate(&self) -> &Certificate {
+    &self.chain[0]
+  }
+}
+
+/// a TLS protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum TlsVersion {
+  /// TLS 1.2
+  #[default]
+  Tls12,
+  /// TLS 1.3
+  Tls13,
+}
+
+impl fmt::Display for TlsVersion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Tls12 => write!(f, "TLSv1.2"),
+      Self::Tls13 => write!(f, "TLSv1.3"),
+    }
+  }
+}
+
+/// what was negotiated for a TLS session
+#[derive(Debug, Clone)]
+pub struct TlsSession {
+  /// protocol version, not reported by `native-tls`
+  pub version: Option<TlsVersion>,
+  /// name of the cipher suite, not reported by `native-tls`
+  pub cipher_suite: Option<String>,
+  /// certificates presented by the server, leaf first, of which `native-tls` only reports the leaf
+  pub chain: Vec<Certificate>,
+}
+
+/// how TLS connections are set up, whichever backend is used
+///
+/// no ALPN protocol is offered, as POP3 has none registered
+#[derive(Clone, Default)]
+pub struct TlsConfig {
+  /// certificates trusted in addition to the system roots with `native-tls`, or to the bundled
+  /// Mozilla roots with `rustls`
+  pub root_certificates: Vec<Certificate>,
+  /// when not empty, the server is trusted if and only if its certificate has one of these
+  /// fingerprints, instead of being verified against the root certificates and the host name
+  pub pinned_fingerprints: Vec<Fingerprint>,
+  /// presented when the server asks for a client certificate
+  pub identity: Option<Identity>,
+  /// oldest protocol version to negotiate
+  pub min_version: TlsVersion,
+}
+
+impl TlsConfig {
+  /// whether `chain` is accepted by the pinned fingerprints
+  fn is_pinned(&self, chain: &[Certificate]) -> bool {
+    chain
+      .first()
+      .is_some_and(|leaf| self.pinned_fingerprints.contains(&leaf.fingerprint()))
+  }
+}
+
+#[test]
+fn test_certificate_from_pem() {
+  let certs = Certificate::from_pem(include_bytes!("../testdata/localhost.pem")).unwrap();
+  assert_eq!(certs.len(), 1);
+  assert_eq!(certs[0].der()[0], 0x30);
+  assert!(Certificate::from_pem(b"").is_err());
+
+  assert!(Identity::from_pem(
+    include_bytes!("../testdata/localhost.pem"),
+    include_bytes!("../testdata/localhost.key")
+  )
+  .is_ok());
+  assert!(Identity::from_pem(
+    include_bytes!("../testdata/localhost.pem"),
+    include_bytes!("../testdata/ca.pem")
+  )
+  .is_err());
+}
+
+#[test]
+fn test_identity_from_pkcs12() {
+  let client = Certificate::from_pem(include_bytes!("../testdata/client.pem")).unwrap();
+  let identity = Identity::from_pkcs12(include_bytes!("../testdata/client.p12"), "secret").unwrap();
+  assert_eq!(identity.certificate(), &client[0]);
+  assert_eq!(
+    identity.key,
+    Identity::from_pem(
+      include_bytes!("../testdata/client.pem"),
+      include_bytes!("../testdata/client.key")
+    )
+    .unwrap()
+    .key
+  );
+  assert!(Identity::from_pkcs12(include_bytes!("../testdata/client.p12"), "wrong").is_err());
+  assert!(Identity::from_pkcs12(b"", "secret").is_err());
+}
+
+#[test]
+fn test_certificate_info() {
+  let cert = &Certificate::from_pem(include_bytes!("../testdata/localhost.pem")).unwrap()[0];
+  let info = cert.info().unwrap();
+  assert_eq!(info.subject, "CN=localhost");
+  assert_eq!(info.issuer, "CN=pop3-client test CA");
+  assert_eq!(info.subject_alt_names, ["localhost", "127.0.0.1", "::1"]);
+  assert!(info.not_before < info.not_after);
+  assert_eq!(info.fingerprint, cert.fingerprint());
+}
+
+#[test]
+fn test_fingerprint() {
+  let fingerprint = Fingerprint(core::array::from_fn(|i| i as u8 * 8));
+  let text = fingerprint.to_string();
+  assert!(text.starts_with("00:08:10:18:"));
+  assert_eq!(text.len(), 32 * 3 - 1);
+  assert_eq!(text.parse::<Fingerprint>().unwrap(), fingerprint);
+  assert_eq!(
+    text
+      .replace(':', "")
+      .to_lowercase()
+      .parse::<Fingerprint>()
+      .unwrap(),
+    fingerprint
+  );
+  assert!(text[3..].parse::<Fingerprint>().is_err());
+  assert!(text.replace('0', "g").parse::<Fingerprint>().is_err());
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use native_tls::{Protocol, TlsConnector};
+
+use super::{Certificate, HandshakeFailed, TlsConfig, TlsSession, TlsVersion, Verify};
+use crate::client::Stream;
+
+/// `der` in PEM, under `label`
+fn pem(label: &str, der: &[u8]) -> Vec<u8> {
+  use base64::{engine::general_purpose::STANDARD, Engine};
+
+  let encoded = STANDARD.encode(der);
+  let mut pem = format!("-----BEGIN {}-----\n", label);
+  for line in encoded.as_bytes().chunks(64) {
+    pem.push_str(std::str::from_utf8(line).unwrap());
+    pem.push('\n');
+  }
+  pem.push_str(&format!("-----END {}-----\n", label));
+  pem.into_bytes()
+}
+
+fn connector(config: &TlsConfig, verify: Verify) -> Result<TlsConnector> {
+  let mut builder = TlsConnector::builder();
+  for cert in &config.root_certificates {
+    builder.add_root_certificate(native_tls::Certificate::from_der(cert.der())?);
+  }
+  if let Some(identity) = &config.identity {
+    let chain: Vec<u8> = identity
+      .chain
+      .iter()
+      .flat_map(|cert| pem("CERTIFICATE", cert.der()))
+      .collect();
+    builder.identity(native_tls::Identity::from_pkcs8(
+      &chain,
+      &pem("PRIVATE KEY", &identity.key),
+    )?);
+  }
+  builder.min_protocol_version(Some(match config.min_version {
+    TlsVersion::Tls12 => Protocol::Tlsv12,
+    TlsVersion::Tls13 => Protocol::Tlsv13,
+  }));
+  // pins are checked once the handshake is done, as the backend offers no hook to do it during
+  if verify == Verify::Probe || !config.pinned_fingerprints.is_empty() {
+    builder.danger_accept_invalid_certs(true);
+    builder.danger_accept_invalid_hostnames(true);
+  }
+  Ok(builder.build()?)
+}
+
+/// the session of `stream`, of which only the leaf certificate is known
+fn session<S: std::io::Read + std::io::Write>(
+  stream: &native_tls::TlsStream<S>,
+  config: &TlsConfig,
+  verify: Verify,
+) -> Result<TlsSession> {
+  let chain: Vec<_> = stream
+    .peer_certificate()?
+    .map(|cert| cert.to_der())
+    .transpose()?
+    .map(Certificate::from_der)
+    .into_iter()
+    .collect();
+  if verify == Verify::Config && !config.pinned_fingerprints.is_empty() && !config.is_pinned(&chain)
+  {
+    return Err(HandshakeFailed(anyhow! {"certificate matches no pinned fingerprint"}).into());
+  }
+  Ok(TlsSession {
+    version: None,
+    cipher_suite: None,
+    chain,
+  })
+}
+
+/// wrap `stream` into a TLS session with `domain`, returning what was negotiated
+pub(crate) async fn connect(
+  domain: &str,
+  stream: Stream,
+  config: &TlsConfig,
+  verify: Verify,
+) -> Result<(Stream, TlsSession)> {
+  let connector = tokio_native_tls::TlsConnector::from(connector(config, verify)?);
+  let stream = connector
+    .connect(domain, stream)
+    .await
+    .map_err(|err| HandshakeFailed(err.into()))?;
+  let session = session(stream.get_ref(), config, verify)?;
+  Ok((Box::pin(stream), session))
+}
+
+/// like [`connect`], blocking on the handshake
+#[cfg(feature = "blocking")]
+pub(crate) fn connect_blocking(
+  domain: &str,
+  stream: crate::blocking::Stream,
+  config: &TlsConfig,
+  verify: Verify,
+) -> Result<(crate::blocking::Stream, TlsSession)> {
+  use native_tls::HandshakeError;
+
+  let stream = match connector(config, verify)?.connect(domain, stream) {
+    Ok(stream) => stream,
+    Err(HandshakeError::Failure(err)) => return Err(HandshakeFailed(err.into()).into()),
+    Err(HandshakeError::WouldBlock(_)) => return Err(anyhow! {"TLS handshake timed out"}),
+  };
+  let session = session(&stream, config, verify)?;
+  Ok((Box::new(stream), session))
+}
+
+/// the server side of a TLS session as `localhost`, for tests
+#[cfg(test)]
+pub(crate) fn accept(
+  stream: std::net::TcpStream,
+) -> Result<native_tls::TlsStream<std::net::TcpStream>> {
+  let identity = native_tls::Identity::from_pkcs8(
+    include_bytes!("../../testdata/localhost.pem"),
+    include_bytes!("../../testdata/localhost.key"),
+  )?;
+  let acceptor = native_tls::TlsAcceptor::new(identity)?;
+  acceptor.accept(stream).map_err(|err| anyhow! {"{}", err})
+}
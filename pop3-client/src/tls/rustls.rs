@@ -0,0 +1,206 @@
+use anyhow::Result;
+use rustls::{
+  client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+  version::{TLS12, TLS13},
+  ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName, SupportedProtocolVersion,
+};
+use std::{sync::Arc, time::SystemTime};
+use tokio_rustls::TlsConnector;
+
+use super::{Certificate, HandshakeFailed, TlsConfig, TlsSession, TlsVersion, Verify};
+use crate::client::Stream;
+
+#[cfg(test)]
+fn certs(pem: &[u8]) -> Result<Vec<rustls::Certificate>> {
+  Ok(
+    rustls_pemfile::certs(&mut &pem[..])?
+      .into_iter()
+      .map(rustls::Certificate)
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+fn private_key(pem: &[u8]) -> Result<PrivateKey> {
+  rustls_pemfile::pkcs8_private_keys(&mut &pem[..])?
+    .into_iter()
+    .next()
+    .map(PrivateKey)
+    .ok_or_else(|| anyhow::anyhow! {"no PKCS#8 private key found in PEM"})
+}
+
+/// checks the server certificate against the pins if there are any, and the roots otherwise
+struct Verifier {
+  roots: WebPkiVerifier,
+  config: TlsConfig,
+  verify: Verify,
+}
+
+impl ServerCertVerifier for Verifier {
+  fn verify_server_cert(
+    &self,
+    end_entity: &rustls::Certificate,
+    intermediates: &[rustls::Certificate],
+    server_name: &ServerName,
+    scts: &mut dyn Iterator<Item = &[u8]>,
+    ocsp_response: &[u8],
+    now: SystemTime,
+  ) -> Result<ServerCertVerified, rustls::Error> {
+    if self.verify == Verify::Probe {
+      return Ok(ServerCertVerified::assertion());
+    }
+    if self.config.pinned_fingerprints.is_empty() {
+      return self.roots.verify_server_cert(
+        end_entity,
+        intermediates,
+        server_name,
+        scts,
+        ocsp_response,
+        now,
+      );
+    }
+    if self
+      .config
+      .is_pinned(&[Certificate::from_der(end_entity.0.clone())])
+    {
+      Ok(ServerCertVerified::assertion())
+    } else {
+      Err(rustls::Error::General(
+        "certificate matches no pinned fingerprint".to_string(),
+      ))
+    }
+  }
+}
+
+fn client_config(config: &TlsConfig, verify: Verify) -> Result<Arc<ClientConfig>> {
+  let mut roots = RootCertStore::empty();
+  roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+    OwnedTrustAnchor::from_subject_spki_name_constraints(
+      anchor.subject,
+      anchor.spki,
+      anchor.name_constraints,
+    )
+  }));
+  for cert in &config.root_certificates {
+    roots.add(&rustls::Certificate(cert.der().to_vec()))?;
+  }
+  let versions: &[&SupportedProtocolVersion] = match config.min_version {
+    TlsVersion::Tls12 => &[&TLS13, &TLS12],
+    TlsVersion::Tls13 => &[&TLS13],
+  };
+  let builder = ClientConfig::builder()
+    .with_safe_default_cipher_suites()
+    .with_safe_default_kx_groups()
+    .with_protocol_versions(versions)?
+    .with_custom_certificate_verifier(Arc::new(Verifier {
+      roots: WebPkiVerifier::new(roots, None),
+      config: config.clone(),
+      verify,
+    }));
+  let config = match &config.identity {
+    Some(identity) => builder.with_client_auth_cert(
+      identity
+        .chain
+        .iter()
+        .map(|cert| rustls::Certificate(cert.der().to_vec()))
+        .collect(),
+      PrivateKey(identity.key.clone()),
+    )?,
+    None => builder.with_no_client_auth(),
+  };
+  Ok(Arc::new(config))
+}
+
+fn session(conn: &rustls::CommonState) -> TlsSession {
+  TlsSession {
+    version: conn.protocol_version().and_then(|version| match version {
+      rustls::ProtocolVersion::TLSv1_2 => Some(TlsVersion::Tls12),
+      rustls::ProtocolVersion::TLSv1_3 => Some(TlsVersion::Tls13),
+      _ => None,
+    }),
+    cipher_suite: conn
+      .negotiated_cipher_suite()
+      .map(|suite| format!("{:?}", suite.suite())),
+    chain: conn
+      .peer_certificates()
+      .unwrap_or_default()
+      .iter()
+      .map(|cert| Certificate::from_der(cert.0.clone()))
+      .collect(),
+  }
+}
+
+/// wrap `stream` into a TLS session with `domain`, returning what was negotiated
+pub(crate) async fn connect(
+  domain: &str,
+  stream: Stream,
+  config: &TlsConfig,
+  verify: Verify,
+) -> Result<(Stream, TlsSession)> {
+  let connector = TlsConnector::from(client_config(config, verify)?);
+  let stream = connector
+    .connect(ServerName::try_from(domain)?, stream)
+    .await
+    .map_err(|err| HandshakeFailed(err.into()))?;
+  let session = session(stream.get_ref().1);
+  Ok((Box::pin(stream), session))
+}
+
+/// like [`connect`], blocking on the handshake
+#[cfg(feature = "blocking")]
+pub(crate) fn connect_blocking(
+  domain: &str,
+  stream: crate::blocking::Stream,
+  config: &TlsConfig,
+  verify: Verify,
+) -> Result<(crate::blocking::Stream, TlsSession)> {
+  let conn = rustls::ClientConnection::new(
+    client_config(config, verify)?,
+    ServerName::try_from(domain)?,
+  )?;
+  let mut tls = rustls::StreamOwned::new(conn, stream);
+  while tls.conn.is_handshaking() {
+    tls
+      .conn
+      .complete_io(&mut tls.sock)
+      .map_err(|err| HandshakeFailed(err.into()))?;
+  }
+  let session = session(&tls.conn);
+  Ok((Box::new(tls), session))
+}
+
+/// the server side of a TLS session as `localhost`, for tests
+#[cfg(test)]
+pub(crate) fn accept(
+  stream: std::net::TcpStream,
+) -> Result<rustls::StreamOwned<rustls::ServerConnection, std::net::TcpStream>> {
+  let config = rustls::ServerConfig::builder()
+    .with_safe_defaults()
+    .with_no_client_auth()
+    .with_single_cert(
+      certs(include_bytes!("../../testdata/localhost.pem"))?,
+      private_key(include_bytes!("../../testdata/localhost.key"))?,
+    )?;
+  let conn = rustls::ServerConnection::new(Arc::new(config))?;
+  Ok(rustls::StreamOwned::new(conn, stream))
+}
+
+/// like [`accept`], requiring a client certificate issued by the test CA
+#[cfg(test)]
+pub(crate) fn accept_authenticated(
+  stream: std::net::TcpStream,
+) -> Result<rustls::StreamOwned<rustls::ServerConnection, std::net::TcpStream>> {
+  let mut roots = RootCertStore::empty();
+  for cert in certs(include_bytes!("../../testdata/ca.pem"))? {
+    roots.add(&cert)?;
+  }
+  let config = rustls::ServerConfig::builder()
+    .with_safe_defaults()
+    .with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed())
+    .with_single_cert(
+      certs(include_bytes!("../../testdata/localhost.pem"))?,
+      private_key(include_bytes!("../../testdata/localhost.key"))?,
+    )?;
+  let conn = rustls::ServerConnection::new(Arc::new(config))?;
+  Ok(rustls::StreamOwned::new(conn, stream))
+}
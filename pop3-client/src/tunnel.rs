@@ -0,0 +1,207 @@
+//! sessions over the standard input and output of a command, like the `plugin` option of
+//! fetchmail, as in `ssh mail.example.com nc localhost 110`
+
+use anyhow::{anyhow, Result};
+use std::{
+  io,
+  pin::Pin,
+  process::{Command, Stdio},
+  task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// a command speaking POP3 on its standard input and output, instead of a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tunnel {
+  /// command line, run by the shell, in which `%h` and `%p` stand for the host and port of the
+  /// server, and `%%` for `%`
+  pub command: String,
+  /// whether the session starts logged in, as with `dovecot --exec-mail pop3` run over `ssh`,
+  /// which greets with `+OK` right in the transaction state
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub preauth: bool,
+}
+
+impl Tunnel {
+  /// a tunnel through `command`, which expects a login
+  pub fn new(command: impl Into<String>) -> Self {
+    Self {
+      command: command.into(),
+      preauth: false,
+    }
+  }
+
+  /// `command` with `%h` and `%p` replaced
+  ///
+  /// as the command line goes through the shell, the host may only be a name or an address
+  fn command_line(&self, host: &str, port: u16) -> Result<String> {
+    let mut line = String::with_capacity(self.command.len());
+    let mut chars = self.command.chars();
+    while let Some(c) = chars.next() {
+      if c != '%' {
+        line.push(c);
+        continue;
+      }
+      match chars.next() {
+        Some('h') => {
+          let valid = |c: char| c.is_ascii_alphanumeric() || ".-_:".contains(c);
+          if !host.chars().all(valid) {
+            return Err(anyhow! {"host {} cannot be passed to a tunnel command", host});
+          }
+          line.push_str(host);
+        }
+        Some('p') => line.push_str(&port.to_string()),
+        Some('%') => line.push('%'),
+        other => {
+          line.push('%');
+          line.extend(other);
+        }
+      }
+    }
+    Ok(line)
+  }
+
+  /// the command to spawn for `host:port`, its standard error left to that of this process,
+  /// where `ssh` may ask for a password
+  fn command(&self, host: &str, port: u16) -> Result<Command> {
+    let line = self.command_line(host, port)?;
+    #[cfg(unix)]
+    let mut command = {
+      let mut command = Command::new("sh");
+      command.arg("-c").arg(line);
+      command
+    };
+    #[cfg(windows)]
+    let mut command = {
+      let mut command = Command::new("cmd");
+      command.arg("/C").arg(line);
+      command
+    };
+    command.stdin(Stdio::piped()).stdout(Stdio::piped());
+    Ok(command)
+  }
+}
+
+fn spawn_failed(tunnel: &Tunnel, err: io::Error) -> anyhow::Error {
+  anyhow! {"cannot run tunnel command {}: {}", tunnel.command, err}
+}
+
+/// the standard input and output of a tunnel command, which is killed when dropped
+pub(crate) struct TunnelStream {
+  // kept for `kill_on_drop`
+  _child: tokio::process::Child,
+  stdin: tokio::process::ChildStdin,
+  stdout: tokio::process::ChildStdout,
+}
+
+/// run `tunnel` for `host:port`
+pub(crate) fn spawn(tunnel: &Tunnel, host: &str, port: u16) -> Result<TunnelStream> {
+  let mut child = tokio::process::Command::from(tunnel.command(host, port)?)
+    .kill_on_drop(true)
+    .spawn()
+    .map_err(|err| spawn_failed(tunnel, err))?;
+  Ok(TunnelStream {
+    stdin: child.stdin.take().expect("stdin is piped"),
+    stdout: child.stdout.take().expect("stdout is piped"),
+    _child: child,
+  })
+}
+
+impl AsyncRead for TunnelStream {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.stdout).poll_read(cx, buf)
+  }
+}
+
+impl AsyncWrite for TunnelStream {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.stdin).poll_write(cx, buf)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.stdin).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.stdin).poll_shutdown(cx)
+  }
+}
+
+/// like [`TunnelStream`], blocking
+#[cfg(feature = "blocking")]
+pub(crate) struct BlockingTunnelStream {
+  child: std::process::Child,
+  stdin: std::process::ChildStdin,
+  stdout: std::process::ChildStdout,
+}
+
+/// like [`spawn`], blocking
+#[cfg(feature = "blocking")]
+pub(crate) fn spawn_blocking(
+  tunnel: &Tunnel,
+  host: &str,
+  port: u16,
+) -> Result<BlockingTunnelStream> {
+  let mut child = tunnel
+    .command(host, port)?
+    .spawn()
+    .map_err(|err| spawn_failed(tunnel, err))?;
+  Ok(BlockingTunnelStream {
+    stdin: child.stdin.take().expect("stdin is piped"),
+    stdout: child.stdout.take().expect("stdout is piped"),
+    child,
+  })
+}
+
+#[cfg(feature = "blocking")]
+impl io::Read for BlockingTunnelStream {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    self.stdout.read(buf)
+  }
+}
+
+#[cfg(feature = "blocking")]
+impl io::Write for BlockingTunnelStream {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.stdin.write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.stdin.flush()
+  }
+}
+
+#[cfg(feature = "blocking")]
+impl Drop for BlockingTunnelStream {
+  fn drop(&mut self) {
+    // the command may already have exited after `QUIT`
+    let _ = self.child.kill();
+    let _ = self.child.wait();
+  }
+}
+
+#[test]
+fn test_tunnel_command_line() {
+  let tunnel = Tunnel::new("ssh %h nc localhost %p # 100%% %x%");
+  assert_eq!(
+    tunnel.command_line("mail.example.com", 110).unwrap(),
+    "ssh mail.example.com nc localhost 110 # 100% %x%"
+  );
+  assert_eq!(
+    Tunnel::new("nc %h %p").command_line("::1", 995).unwrap(),
+    "nc ::1 995"
+  );
+  assert!(tunnel.command_line("example.com;rm", 110).is_err());
+  assert!(Tunnel::new("dovecot --exec-mail pop3")
+    .command_line("example.com;rm", 110)
+    .is_ok());
+}
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Error};
+use std::path::Path;
+use tempfile::TempDir;
+
+/// extensions of files that the desktop would run rather than show
+const EXECUTABLE: &[&str] = &[
+  "app", "appimage", "bat", "bash", "cmd", "com", "command", "cpl", "csh", "desktop", "exe", "hta",
+  "jar", "js", "jse", "ksh", "lnk", "msi", "msp", "pif", "pl", "ps1", "py", "rb", "reg", "run",
+  "scr", "sh", "url", "vb", "vbe", "vbs", "wsf", "wsh", "zsh",
+];
+
+/// a directory only readable by the user, for attachments handed to other programs, removed when
+/// dropped
+pub fn private_dir() -> Result<TempDir, Error> {
+  let mut builder = tempfile::Builder::new();
+  builder.prefix("pop3-client-");
+  #[cfg(unix)]
+  builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o700));
+  Ok(builder.tempdir()?)
+}
+
+/// a file name for an attachment named `filename` by the sender, falling back to `fallback`,
+/// refusing the types that would be run when opened
+pub fn open_name(filename: Option<&str>, fallback: String) -> Result<String, Error> {
+  let name: String = filename
+    .unwrap_or_default()
+    .chars()
+    .take(200)
+    .map(|c| match c {
+      '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .collect();
+  // a leading dot hides the file, and Windows ignores trailing dots and spaces in the extension
+  let name = match name.trim_start_matches('.').trim_end_matches(['.', ' ']) {
+    "" => fallback,
+    name => name.to_string(),
+  };
+  let extension = Path::new(&name)
+    .extension()
+    .map(|ext| ext.to_string_lossy().to_lowercase());
+  if let Some(ext) = extension.filter(|ext| EXECUTABLE.contains(&ext.as_str())) {
+    return Err(anyhow!(
+      "refusing to open a .{} attachment, save it instead",
+      ext
+    ));
+  }
+  Ok(name)
+}
+
+#[test]
+fn test_open_name() {
+  let fallback = || "attachment-1-2".to_string();
+  assert_eq!(
+    open_name(Some("report.pdf"), fallback()).unwrap(),
+    "report.pdf"
+  );
+  assert_eq!(
+    open_name(Some("../../.bashrc"), fallback()).unwrap(),
+    "_.._.bashrc"
+  );
+  assert_eq!(
+    open_name(Some("a\r\nb.txt"), fallback()).unwrap(),
+    "a__b.txt"
+  );
+  assert_eq!(
+    open_name(Some("..."), fallback()).unwrap(),
+    "attachment-1-2"
+  );
+  assert_eq!(open_name(None, fallback()).unwrap(), "attachment-1-2");
+  assert!(open_name(Some("invoice.pdf.desktop"), fallback()).is_err());
+  assert!(open_name(Some("SETUP.EXE"), fallback()).is_err());
+  assert!(open_name(Some("run.sh. . "), fallback()).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_private_dir() {
+  use std::os::unix::fs::PermissionsExt;
+
+  let dir = private_dir().unwrap();
+  let mode = std::fs::metadata(dir.path()).unwrap().permissions().mode();
+  assert_eq!(mode & 0o777, 0o700);
+}
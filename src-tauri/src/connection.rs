@@ -0,0 +1,63 @@
+use pop3_client::{CertificateInfo, ConnectionInfo, Security};
+use serde::{Deserialize, Serialize};
+
+/// how the current connection was set up, as shown to the user
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnectionDetails {
+  /// `None` through a tunnel command
+  pub remote_ip: Option<String>,
+  pub remote_port: Option<u16>,
+  /// whether the session was upgraded to TLS with `STLS`
+  pub stls: bool,
+  pub greeting: String,
+  /// `None` without TLS, or when the TLS backend does not report it
+  pub tls_version: Option<String>,
+  pub cipher_suite: Option<String>,
+  /// certificates presented by the server, leaf first
+  pub peer_certificates: Vec<CertificateInfo>,
+  /// whether the session started logged in, so that there is no login to do
+  pub preauthenticated: bool,
+}
+
+impl From<&ConnectionInfo> for ConnectionDetails {
+  fn from(info: &ConnectionInfo) -> Self {
+    let tls = info.tls.as_ref();
+    Self {
+      remote_ip: info.remote_addr.map(|addr| addr.ip().to_string()),
+      remote_port: info.remote_addr.map(|addr| addr.port()),
+      stls: info.security == Security::StartTls,
+      greeting: info.greeting.clone(),
+      tls_version: tls
+        .and_then(|tls| tls.version)
+        .map(|version| version.to_string()),
+      cipher_suite: tls.and_then(|tls| tls.cipher_suite.clone()),
+      peer_certificates: tls
+        .map(|tls| {
+          tls
+            .chain
+            .iter()
+            .filter_map(|cert| cert.info().ok())
+            .collect()
+        })
+        .unwrap_or_default(),
+      preauthenticated: info.preauthenticated,
+    }
+  }
+}
+
+#[test]
+fn test_connection_details_stls() {
+  let info = |security| ConnectionInfo {
+    remote_addr: Some("127.0.0.1:110".parse().unwrap()),
+    security,
+    greeting: "ready".to_string(),
+    tls: Some(pop3_client::TlsSession {
+      version: None,
+      cipher_suite: None,
+      chain: vec![],
+    }),
+    preauthenticated: false,
+  };
+  assert!(ConnectionDetails::from(&info(Security::StartTls)).stls);
+  assert!(!ConnectionDetails::from(&info(Security::Tls)).stls);
+}
@@ -1,15 +1,47 @@
+use pop3_client::{CertificateInfo, UntrustedCertificate};
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ErrorMsg {
   msg: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  tls_certificate_prompt: Option<TlsCertificatePrompt>,
+}
+
+/// an untrusted server certificate, which the user may trust with `trust_certificate`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TlsCertificatePrompt {
+  reason: String,
+  /// certificates presented by the server, leaf first
+  chain: Vec<CertificateInfo>,
 }
 
 impl<T: Display> From<T> for ErrorMsg {
   fn from(err: T) -> Self {
     Self {
       msg: err.to_string(),
+      tls_certificate_prompt: None,
+    }
+  }
+}
+
+impl ErrorMsg {
+  /// the error of a failed connection, prompting the user about an untrusted certificate
+  pub fn from_connect(err: anyhow::Error) -> Self {
+    match err.downcast::<UntrustedCertificate>() {
+      Ok(untrusted) => Self {
+        msg: untrusted.to_string(),
+        tls_certificate_prompt: Some(TlsCertificatePrompt {
+          chain: untrusted
+            .chain
+            .iter()
+            .filter_map(|cert| cert.info().ok())
+            .collect(),
+          reason: untrusted.reason,
+        }),
+      },
+      Err(err) => err.into(),
     }
   }
 }
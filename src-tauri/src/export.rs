@@ -0,0 +1,208 @@
+use anyhow::Error;
+use mailparse::{addrparse_header, parse_headers, MailAddr, MailHeaderMap};
+use pop3_client::email::Date;
+use std::{
+  fs::OpenOptions,
+  io::{ErrorKind, Write},
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// `timestamp` in UTC, formatted like C's `asctime` as mbox `From ` lines expect
+fn asctime(timestamp: i64) -> String {
+  let days = timestamp.div_euclid(86400);
+  let secs = timestamp.rem_euclid(86400);
+  // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+  format!(
+    "{} {} {:>2} {:02}:{:02}:{:02} {}",
+    WEEKDAYS[days.rem_euclid(7) as usize],
+    MONTHS[month as usize - 1],
+    day,
+    secs / 3600,
+    secs / 60 % 60,
+    secs % 60,
+    year
+  )
+}
+
+fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |now| now.as_secs() as i64)
+}
+
+/// envelope sender and date of the `From ` line, from `Return-Path:` or `From:` and `Date:`,
+/// falling back to `Received:` and then to the time of the export
+fn envelope(mail: &[u8]) -> (String, i64) {
+  let headers = match parse_headers(mail) {
+    Ok((headers, _)) => headers,
+    Err(_) => return ("MAILER-DAEMON".to_string(), now()),
+  };
+  let sender = ["Return-Path", "From"]
+    .iter()
+    .filter_map(|key| headers.get_first_header(key))
+    .filter_map(|header| addrparse_header(header).ok())
+    .find_map(|list| {
+      list.iter().find_map(|addr| match addr {
+        MailAddr::Single(info) if !info.addr.trim().is_empty() => Some(info.addr.clone()),
+        MailAddr::Group(group) => group.addrs.first().map(|info| info.addr.clone()),
+        _ => None,
+      })
+    })
+    // the sender is a single word in the `From ` line
+    .map(|addr| addr.split_whitespace().collect::<String>())
+    .filter(|addr| !addr.is_empty())
+    .unwrap_or_else(|| "MAILER-DAEMON".to_string());
+  let timestamp = Date::of_headers(&headers).map_or_else(now, |date| date.timestamp());
+  (sender, timestamp)
+}
+
+/// whether `line` needs one more `>` in mboxrd, i.e. it matches `^>*From `
+pub(crate) fn is_from_line(line: &[u8]) -> bool {
+  let quotes = line.iter().take_while(|&&c| c == b'>').count();
+  line[quotes..].starts_with(b"From ")
+}
+
+/// body of an mboxrd entry: `From ` lines quoted, line endings converted to LF and a blank line
+/// separating it from the next entry
+fn mboxrd_body(mail: &[u8]) -> Vec<u8> {
+  let mut body = Vec::with_capacity(mail.len() + 2);
+  let mail = mail.strip_suffix(b"\n").unwrap_or(mail);
+  for line in mail.split(|&c| c == b'\n') {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if is_from_line(line) {
+      body.push(b'>');
+    }
+    body.extend_from_slice(line);
+    body.push(b'\n');
+  }
+  body.push(b'\n');
+  body
+}
+
+/// `mail` as an mboxrd entry, starting with its `From ` line
+pub fn mboxrd_entry(mail: &[u8]) -> Vec<u8> {
+  let (sender, timestamp) = envelope(mail);
+  let mut entry = format!("From {} {}\n", sender, asctime(timestamp)).into_bytes();
+  entry.extend(mboxrd_body(mail));
+  entry
+}
+
+/// append `mails` to the mboxrd file at `path`, creating it if needed
+pub fn append_mbox<'a>(
+  path: &Path,
+  mails: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<(), Error> {
+  let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+  for mail in mails {
+    file.write_all(&mboxrd_entry(mail))?;
+  }
+  file.flush()?;
+  Ok(())
+}
+
+/// file name for `mail`, from its `Message-ID:` with anything but letters, digits and `.-_@+`
+/// replaced, so that exports of the same mail in different sessions agree
+fn eml_stem(mail: &[u8]) -> String {
+  let id = parse_headers(mail)
+    .ok()
+    .and_then(|(headers, _)| headers.get_first_value("Message-ID"))
+    .unwrap_or_default();
+  let stem: String = id
+    .trim()
+    .trim_start_matches('<')
+    .trim_end_matches('>')
+    .chars()
+    .take(100)
+    .map(|c| match c {
+      'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' | '@' | '+' => c,
+      _ => '_',
+    })
+    .collect();
+  match stem.trim_start_matches('.') {
+    "" => "mail".to_string(),
+    stem => stem.to_string(),
+  }
+}
+
+/// write `mail` unchanged to a new file in `dir` named after its `Message-ID:`, numbered rather
+/// than overwriting another file, returning the path written
+pub fn write_eml(dir: &Path, mail: &[u8]) -> Result<PathBuf, Error> {
+  let stem = eml_stem(mail);
+  for n in 0.. {
+    let path = match n {
+      0 => dir.join(format!("{}.eml", stem)),
+      n => dir.join(format!("{}-{}.eml", stem, n)),
+    };
+    match OpenOptions::new().write(true).create_new(true).open(&path) {
+      Ok(mut file) => {
+        file.write_all(mail)?;
+        return Ok(path);
+      }
+      Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+      Err(err) => return Err(err.into()),
+    }
+  }
+  unreachable!("some name is free")
+}
+
+#[test]
+fn test_asctime() {
+  assert_eq!(asctime(0), "Thu Jan  1 00:00:00 1970");
+  assert_eq!(asctime(951782400), "Tue Feb 29 00:00:00 2000");
+  assert_eq!(asctime(1700000000), "Tue Nov 14 22:13:20 2023");
+  assert_eq!(asctime(-1), "Wed Dec 31 23:59:59 1969");
+}
+
+#[test]
+fn test_mboxrd_quoting() {
+  let mail =
+    b"Subject: hi\r\n\r\nFrom here\r\n>From there\r\n>>From everywhere\r\n From \r\nFrom:\r\n";
+  assert_eq!(
+    mboxrd_body(mail),
+    b"Subject: hi\n\n>From here\n>>From there\n>>>From everywhere\n From \nFrom:\n\n"
+  );
+  // an unterminated last line still gets its newline
+  assert_eq!(mboxrd_body(b"a\r\nb"), b"a\nb\n\n");
+}
+
+#[test]
+fn test_mboxrd_entry() {
+  let mail =
+    b"From: Alice <alice@example.com>\r\nDate: Thu, 1 Jan 1970 00:00:00 +0000\r\n\r\nhi\r\n";
+  assert_eq!(
+    mboxrd_entry(mail),
+    &b"From alice@example.com Thu Jan  1 00:00:00 1970\n\
+       From: Alice <alice@example.com>\nDate: Thu, 1 Jan 1970 00:00:00 +0000\n\nhi\n\n"[..]
+  );
+  assert!(mboxrd_entry(b"\r\nno headers\r\n").starts_with(b"From MAILER-DAEMON "));
+  // not the epoch for a mail without a usable date
+  let undated = mboxrd_entry(b"Date: yesterday\r\n\r\nhi\r\n");
+  assert!(!undated.starts_with(b"From MAILER-DAEMON Thu Jan  1 00:00:00 1970"));
+  let received = b"Received: from a by b; Tue, 14 Nov 2023 22:13:20 +0000\r\n\r\nhi\r\n";
+  assert!(mboxrd_entry(received).starts_with(b"From MAILER-DAEMON Tue Nov 14 22:13:20 2023\n"));
+}
+
+#[test]
+fn test_eml_stem() {
+  assert_eq!(
+    eml_stem(b"Message-ID: <a1.b/c@example.com>\r\n\r\n"),
+    "a1.b_c@example.com"
+  );
+  assert_eq!(eml_stem(b"Message-ID: <../..>\r\n\r\n"), "_..");
+  assert_eq!(eml_stem(b"Subject: none\r\n\r\n"), "mail");
+}
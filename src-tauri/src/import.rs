@@ -0,0 +1,173 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::{
+  io::{self, BufRead},
+  path::{Path, PathBuf},
+};
+
+use crate::{export::is_from_line, store::Folder};
+
+/// how `From ` lines in message bodies were quoted when the mbox was written
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MboxFormat {
+  /// only `From ` is quoted, so `>From ` in the original cannot be told apart
+  Mboxo,
+  /// every `>*From ` is quoted with one more `>`
+  Mboxrd,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Progress {
+  /// number of mails to import, unknown for an mbox file until it has been read
+  total: Option<usize>,
+  done: usize,
+  imported: usize,
+  /// mails skipped because their Message-ID is already in the folder
+  duplicates: usize,
+  /// mails that could not be parsed or stored
+  failed: usize,
+}
+
+/// mails of an mbox file, read line by line, with line endings converted to CRLF and `From `
+/// lines unquoted
+pub struct Mbox<R> {
+  reader: R,
+  format: MboxFormat,
+  line: Vec<u8>,
+  mail: Option<Vec<u8>>,
+  /// `From ` only starts a new mail at the start of the file or after a blank line
+  after_blank: bool,
+}
+
+impl<R: BufRead> Mbox<R> {
+  pub fn new(reader: R, format: MboxFormat) -> Self {
+    Self {
+      reader,
+      format,
+      line: vec![],
+      mail: None,
+      after_blank: true,
+    }
+  }
+}
+
+/// drop the blank line separating a mail from the next `From ` line
+fn end_mail(mut mail: Vec<u8>) -> Vec<u8> {
+  if mail.ends_with(b"\r\n\r\n") {
+    mail.truncate(mail.len() - 2);
+  }
+  mail
+}
+
+impl<R: BufRead> Iterator for Mbox<R> {
+  type Item = io::Result<Vec<u8>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      self.line.clear();
+      match self.reader.read_until(b'\n', &mut self.line) {
+        Ok(0) => return self.mail.take().map(|mail| Ok(end_mail(mail))),
+        Ok(_) => {}
+        Err(err) => return Some(Err(err)),
+      }
+      let line = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+      let line = line.strip_suffix(b"\r").unwrap_or(line);
+      if self.after_blank && line.starts_with(b"From ") {
+        self.after_blank = false;
+        if let Some(mail) = self.mail.replace(vec![]) {
+          return Some(Ok(end_mail(mail)));
+        }
+        continue;
+      }
+      self.after_blank = line.is_empty();
+      if let Some(mail) = self.mail.as_mut() {
+        let quoted = match self.format {
+          MboxFormat::Mboxo => line.starts_with(b">From "),
+          MboxFormat::Mboxrd => line.starts_with(b">") && is_from_line(line),
+        };
+        mail.extend_from_slice(if quoted { &line[1..] } else { line });
+        mail.extend_from_slice(b"\r\n");
+      }
+    }
+  }
+}
+
+/// paths of the mails delivered to the Maildir at `dir`, ordered by filename
+pub fn maildir_paths(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+  let mut paths = vec![];
+  for subdir in ["new", "cur"] {
+    for entry in std::fs::read_dir(dir.join(subdir))? {
+      let entry = entry?;
+      if entry.file_type()?.is_file() && !entry.file_name().to_string_lossy().starts_with('.') {
+        paths.push(entry.path());
+      }
+    }
+  }
+  paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+  Ok(paths)
+}
+
+/// insert `mails` into `folder` as they are read, calling `progress` after each one
+///
+/// stops at the first mail that cannot be read, as the rest of the source is likely unreadable
+/// too, keeping the mails imported so far.
+pub fn import(
+  folder: &mut Folder,
+  mails: impl Iterator<Item = io::Result<Vec<u8>>>,
+  total: Option<usize>,
+  mut progress: impl FnMut(&Progress),
+) -> Result<Progress, Error> {
+  let mut state = Progress {
+    total,
+    ..Progress::default()
+  };
+  for mail in mails {
+    match folder.insert(&mail?) {
+      Ok(Some(_)) => state.imported += 1,
+      Ok(None) => state.duplicates += 1,
+      Err(_) => state.failed += 1,
+    }
+    state.done += 1;
+    progress(&state);
+  }
+  state.total = Some(state.done);
+  Ok(state)
+}
+
+#[cfg(test)]
+fn split_mbox(bytes: &[u8], format: MboxFormat) -> Vec<Vec<u8>> {
+  Mbox::new(bytes, format).collect::<io::Result<_>>().unwrap()
+}
+
+#[test]
+fn test_split_mbox() {
+  let mbox = b"From alice@example.com Thu Jan  1 00:00:00 1970\n\
+    Subject: one\n\n>From here\n>>From there\nFrom: nobody\n\n\
+    From bob@example.com Thu Jan  1 00:00:00 1970\n\
+    Subject: two\n\nbye\n";
+  assert_eq!(
+    split_mbox(mbox, MboxFormat::Mboxrd),
+    vec![
+      b"Subject: one\r\n\r\nFrom here\r\n>From there\r\nFrom: nobody\r\n".to_vec(),
+      b"Subject: two\r\n\r\nbye\r\n".to_vec(),
+    ]
+  );
+  assert_eq!(
+    split_mbox(mbox, MboxFormat::Mboxo)[0],
+    b"Subject: one\r\n\r\nFrom here\r\n>>From there\r\nFrom: nobody\r\n"
+  );
+}
+
+#[test]
+fn test_mboxrd_round_trip() {
+  let mails = [
+    &b"From: Alice <alice@example.com>\r\n\r\nFrom here\r\n>From there\r\n\r\n"[..],
+    &b"Subject: two\r\n\r\n\r\nFrom the start\r\n"[..],
+  ];
+  let mbox: Vec<u8> = mails
+    .iter()
+    .flat_map(|mail| crate::export::mboxrd_entry(mail))
+    .collect();
+  assert_eq!(split_mbox(&mbox, MboxFormat::Mboxrd), mails);
+}
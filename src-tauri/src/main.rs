@@ -3,17 +3,42 @@
   windows_subsystem = "windows"
 )]
 
-mod email;
+mod attachment;
+mod connection;
 mod error;
-mod pop3;
+mod export;
+mod import;
+mod store;
 
 use anyhow::Result;
+use bytes::Bytes;
+use pop3_cli::{
+  account::{account_path, fetchids_path, pins_path, Account, Pins, TlsSettings},
+  delivery::{self, FetchIds, Target, TargetConfig},
+};
+use pop3_client::{
+  email::{self, Attachment, Email},
+  Certificate, Pop3, Security, Tunnel, UntrustedCertificate,
+};
+use std::{
+  collections::{BTreeMap, HashMap},
+  fs::File,
+  io::BufReader,
+  path::PathBuf,
+};
 use tauri::async_runtime::Mutex;
+use tempfile::TempDir;
 
-use crate::{email::Email, error::ErrorMsg, pop3::Pop3};
+use crate::{
+  connection::ConnectionDetails,
+  error::ErrorMsg,
+  import::{Mbox, MboxFormat, Progress},
+  store::Folder,
+};
 
 mod msg_command {
-  use crate::{error::ErrorMsg, pop3::Msg};
+  use crate::error::ErrorMsg;
+  use pop3_client::Msg;
 
   #[tauri::command]
   pub fn user_msg(name: &str) -> Result<String, ErrorMsg> {
@@ -29,6 +54,13 @@ mod msg_command {
     Ok(msg.into_string())
   }
 
+  #[tauri::command]
+  pub fn auth_external_msg(authzid: Option<&str>) -> Result<String, ErrorMsg> {
+    let mut msg = Msg::default();
+    msg.auth_external(authzid)?;
+    Ok(msg.into_string())
+  }
+
   #[tauri::command]
   pub fn stat_msg() -> Result<String, ErrorMsg> {
     let mut msg = Msg::default();
@@ -62,28 +94,108 @@ mod msg_command {
 struct State {
   connection: Option<Pop3>,
   addr: String,
+  security: Security,
+  lenient: bool,
+  tls: TlsSettings,
+  proxy: Option<String>,
+  tunnel: Option<Tunnel>,
+  /// delivery targets of the account file, by name
+  targets: BTreeMap<String, TargetConfig>,
   name: String,
   pass: String,
+  /// logged in with SASL EXTERNAL
+  external: bool,
+  /// raw mails retrieved in this session, by message number
+  mails: HashMap<u64, Bytes>,
+  /// private directory of the attachments opened in other programs
+  attachments: Option<TempDir>,
+  /// server address and certificate of the last connection refused for an untrusted
+  /// certificate, the only one `trust_certificate` pins
+  untrusted: Option<(String, Certificate)>,
+}
+
+impl State {
+  fn mail(&self, id: u64) -> Result<&Bytes, ErrorMsg> {
+    Ok(self.mails.get(&id).ok_or("mail not retrieved")?)
+  }
+
+  /// raw mail `id`, retrieving it from the server unless it is already cached
+  async fn fetch(&mut self, id: u64) -> Result<Bytes, ErrorMsg> {
+    if let Some(mail) = self.mails.get(&id) {
+      return Ok(mail.clone());
+    }
+    let connection = self
+      .connection
+      .as_mut()
+      .ok_or("no pop3 server connection")?;
+    let (mail, _) = connection.retr(id).await?;
+    self.mails.insert(id, mail.clone());
+    Ok(mail)
+  }
 }
 
 #[tauri::command]
 async fn connect(
   state: tauri::State<'_, Mutex<State>>,
   addr: String,
-  with_tls: bool,
+  security: Security,
+  lenient: Option<bool>,
 ) -> Result<String, ErrorMsg> {
   let mut state = state.lock().await;
   if let Some(connection) = state.connection.take() {
     let _ = connection.quit().await;
   }
-
-  let (pop3, welcome_msg) = Pop3::new(addr.as_str(), with_tls).await?;
+  state.untrusted = None;
+
+  // TLS, proxy, tunnel and delivery settings are only configured in the account file
+  let mut saved = Account::saved_for(&account_path()?, &addr)?;
+  saved.security = security;
+  saved.lenient = lenient.unwrap_or(false);
+  let mut options = saved.connect_options()?;
+  let pins = Pins::load(&pins_path()?)?;
+  options
+    .tls
+    .pinned_fingerprints
+    .extend_from_slice(pins.get(&addr));
+  let (pop3, welcome_msg) = match Pop3::connect(addr.as_str(), &options).await {
+    Ok(connected) => connected,
+    Err(err) => {
+      state.untrusted = err
+        .downcast_ref::<UntrustedCertificate>()
+        .and_then(|untrusted| untrusted.chain.first())
+        .map(|cert| (addr.clone(), cert.clone()));
+      return Err(ErrorMsg::from_connect(err));
+    }
+  };
   state.connection = Some(pop3);
   state.addr = addr;
+  state.security = saved.security;
+  state.lenient = saved.lenient;
+  state.tls = saved.tls;
+  state.proxy = saved.proxy;
+  state.tunnel = saved.tunnel;
+  state.targets = saved.targets;
+  state.external = false;
+  state.mails.clear();
 
   Ok(welcome_msg)
 }
 
+/// how the current connection was set up
+#[tauri::command]
+async fn connection_info(
+  state: tauri::State<'_, Mutex<State>>,
+) -> Result<ConnectionDetails, ErrorMsg> {
+  let state = state.lock().await;
+
+  let connection = state
+    .connection
+    .as_ref()
+    .ok_or("no pop3 server connection")?;
+
+  Ok(connection.connection_info().into())
+}
+
 #[tauri::command]
 async fn user(state: tauri::State<'_, Mutex<State>>, name: String) -> Result<String, ErrorMsg> {
   let mut state = state.lock().await;
@@ -93,7 +205,10 @@ async fn user(state: tauri::State<'_, Mutex<State>>, name: String) -> Result<Str
     .as_mut()
     .ok_or("no pop3 server connection")?;
 
-  Ok(connection.user(&name).await?)
+  let msg = connection.user(&name).await?;
+  state.name = name;
+
+  Ok(msg)
 }
 
 #[tauri::command]
@@ -105,7 +220,30 @@ async fn pass(state: tauri::State<'_, Mutex<State>>, secret: String) -> Result<S
     .as_mut()
     .ok_or("no pop3 server connection")?;
 
-  Ok(connection.pass(&secret).await?)
+  let msg = connection.pass(&secret).await?;
+  state.pass = secret;
+
+  Ok(msg)
+}
+
+/// log in with the client certificate, as `authzid` if given
+#[tauri::command]
+async fn auth_external(
+  state: tauri::State<'_, Mutex<State>>,
+  authzid: Option<String>,
+) -> Result<String, ErrorMsg> {
+  let mut state = state.lock().await;
+
+  let connection = state
+    .connection
+    .as_mut()
+    .ok_or("no pop3 server connection")?;
+
+  let msg = connection.auth_external(authzid.as_deref()).await?;
+  state.name = authzid.unwrap_or_default();
+  state.external = true;
+
+  Ok(msg)
 }
 
 #[tauri::command]
@@ -145,8 +283,291 @@ async fn retr(state: tauri::State<'_, Mutex<State>>, id: u64) -> Result<(Email,
     .ok_or("no pop3 server connection")?;
 
   let (raw_email, msg) = connection.retr(id).await?;
+  let email: Email = raw_email[..].try_into()?;
+  state.mails.insert(id, raw_email);
+
+  Ok((email, msg))
+}
+
+#[tauri::command]
+async fn list_attachments(
+  state: tauri::State<'_, Mutex<State>>,
+  id: u64,
+) -> Result<Vec<Attachment>, ErrorMsg> {
+  let state = state.lock().await;
+
+  let email: Email = state.mail(id)?[..].try_into()?;
+
+  Ok(email.attachments())
+}
+
+#[tauri::command]
+async fn save_attachment(
+  state: tauri::State<'_, Mutex<State>>,
+  id: u64,
+  part: usize,
+  path: PathBuf,
+) -> Result<(), ErrorMsg> {
+  let state = state.lock().await;
+
+  let content = email::part_content(state.mail(id)?, part)?;
+  std::fs::write(path, content)?;
+
+  Ok(())
+}
+
+#[tauri::command]
+async fn open_attachment(
+  state: tauri::State<'_, Mutex<State>>,
+  id: u64,
+  part: usize,
+) -> Result<(), ErrorMsg> {
+  let mut state = state.lock().await;
+
+  let raw_email = state.mail(id)?;
+  let email: Email = raw_email[..].try_into()?;
+  let attachment = email
+    .attachments()
+    .into_iter()
+    .find(|attachment| attachment.part() == part)
+    .ok_or("no such attachment")?;
+  let filename =
+    attachment::open_name(attachment.filename(), format!("attachment-{}-{}", id, part))?;
+  let content = email::part_content(raw_email, part)?;
+
+  if state.attachments.is_none() {
+    state.attachments = Some(attachment::private_dir()?);
+  }
+  let root = state
+    .attachments
+    .as_ref()
+    .ok_or("no attachment directory")?;
+  // a directory of its own for each attachment, as the names of different mails can collide
+  let dir = tempfile::Builder::new()
+    .prefix(&format!("{}-{}-", id, part))
+    .tempdir_in(root.path())?
+    .keep();
+  let path = dir.join(filename);
+  std::fs::write(&path, content)?;
+  tauri::api::shell::open(path.to_string_lossy().to_string(), None)?;
+
+  Ok(())
+}
+
+#[tauri::command]
+async fn raw_source(state: tauri::State<'_, Mutex<State>>, id: u64) -> Result<String, ErrorMsg> {
+  let state = state.lock().await;
+
+  Ok(email::decode_source(state.mail(id)?))
+}
+
+#[tauri::command]
+async fn save_raw_source(
+  state: tauri::State<'_, Mutex<State>>,
+  id: u64,
+  path: PathBuf,
+) -> Result<(), ErrorMsg> {
+  let state = state.lock().await;
+
+  std::fs::write(path, state.mail(id)?)?;
+
+  Ok(())
+}
+
+#[tauri::command]
+async fn export_eml(
+  state: tauri::State<'_, Mutex<State>>,
+  ids: Vec<u64>,
+  dir: PathBuf,
+) -> Result<Vec<PathBuf>, ErrorMsg> {
+  let mut state = state.lock().await;
+
+  std::fs::create_dir_all(&dir)?;
+  let mut paths = vec![];
+  for id in ids {
+    let mail = state.fetch(id).await?;
+    paths.push(export::write_eml(&dir, &mail)?);
+  }
+
+  Ok(paths)
+}
+
+#[tauri::command]
+async fn export_mbox(
+  state: tauri::State<'_, Mutex<State>>,
+  ids: Vec<u64>,
+  path: PathBuf,
+) -> Result<(), ErrorMsg> {
+  let mut state = state.lock().await;
+
+  let mut mails = vec![];
+  for id in ids {
+    mails.push(state.fetch(id).await?);
+  }
+  export::append_mbox(&path, mails.iter().map(|mail| &mail[..]))?;
+
+  Ok(())
+}
+
+/// import `mails` into the local `folder`, reporting progress as `import-progress` events
+fn import_into(
+  window: &tauri::Window,
+  folder: &str,
+  mails: impl Iterator<Item = std::io::Result<Vec<u8>>>,
+  total: Option<usize>,
+) -> Result<Progress, ErrorMsg> {
+  let mut folder = Folder::open_local(folder)?;
+  Ok(import::import(&mut folder, mails, total, |progress| {
+    let _ = window.emit("import-progress", progress.clone());
+  })?)
+}
+
+/// run an import on the blocking thread pool, as reading and parsing a whole mailbox would hold
+/// up the other commands
+async fn run_import(
+  import: impl FnOnce() -> Result<Progress, ErrorMsg> + Send + 'static,
+) -> Result<Progress, ErrorMsg> {
+  tokio::task::spawn_blocking(import).await?
+}
+
+#[tauri::command]
+async fn import_mbox(
+  window: tauri::Window,
+  path: PathBuf,
+  folder: String,
+  format: MboxFormat,
+) -> Result<Progress, ErrorMsg> {
+  run_import(move || {
+    let mbox = Mbox::new(BufReader::new(File::open(path)?), format);
+    import_into(&window, &folder, mbox, None)
+  })
+  .await
+}
+
+#[tauri::command]
+async fn import_maildir(
+  window: tauri::Window,
+  path: PathBuf,
+  folder: String,
+) -> Result<Progress, ErrorMsg> {
+  run_import(move || {
+    let paths = import::maildir_paths(&path)?;
+    let total = paths.len();
+    import_into(
+      &window,
+      &folder,
+      paths.into_iter().map(std::fs::read),
+      Some(total),
+    )
+  })
+  .await
+}
+
+#[tauri::command]
+fn list_folders() -> Result<Vec<String>, ErrorMsg> {
+  Ok(store::list_local()?)
+}
+
+/// numbers and sizes of the mails in the local `folder`
+#[tauri::command]
+fn list_folder(folder: &str) -> Result<Vec<(u64, u64)>, ErrorMsg> {
+  Ok(Folder::open_local(folder)?.list())
+}
+
+/// mail `id` of the local `folder`, with its source
+#[tauri::command]
+fn read_folder_mail(folder: &str, id: u64) -> Result<(Email, String), ErrorMsg> {
+  let raw_email = Folder::open_local(folder)?.read(id)?;
+  let email: Email = raw_email[..].try_into()?;
+  Ok((email, email::decode_source(&raw_email)))
+}
+
+/// deliver every mail to the target of the account file named `target`, for the user logged in
+#[tauri::command]
+async fn fetch_mail(
+  state: tauri::State<'_, Mutex<State>>,
+  target: String,
+  keep: bool,
+) -> Result<Vec<u64>, ErrorMsg> {
+  let mut state = state.lock().await;
+
+  let config = state
+    .targets
+    .get(&target)
+    .ok_or_else(|| format!("no delivery target {} in the account file", target))?;
+  let mut target = Target::open(config).await?;
+  let mut fetched = FetchIds::load(&fetchids_path(&state.addr, &state.name)?)?;
+  let recipient = state.name.clone();
+  let connection = state
+    .connection
+    .as_mut()
+    .ok_or("no pop3 server connection")?;
+  let delivered = delivery::fetch(connection, &mut target, &recipient, keep, &mut fetched).await;
+  target.close().await?;
+  let delivered = delivered?;
+  if !keep {
+    for id in delivered.iter() {
+      state.mails.remove(id);
+    }
+  }
+
+  Ok(delivered)
+}
+
+/// save the account of the current session for the next login and `pop3-cli`
+#[tauri::command]
+async fn save_account(
+  state: tauri::State<'_, Mutex<State>>,
+  save_password: bool,
+) -> Result<(), ErrorMsg> {
+  let state = state.lock().await;
+
+  let account = Account {
+    addr: state.addr.clone(),
+    security: state.security,
+    lenient: state.lenient,
+    name: state.name.clone(),
+    pass: save_password.then(|| state.pass.clone()),
+    external: state.external,
+    tls: state.tls.clone(),
+    proxy: state.proxy.clone(),
+    tunnel: state.tunnel.clone(),
+    targets: state.targets.clone(),
+  };
+  account.save(&account_path()?)?;
+
+  Ok(())
+}
 
-  Ok((raw_email.try_into()?, msg))
+/// trust the certificate the server presented to the last `connect` from now on, which failed
+/// because it could not be verified
+///
+/// only that certificate can be pinned, so that the webview cannot make the app trust any other.
+/// The pin is saved by server address apart from the account file, which may hold the account of
+/// another server
+#[tauri::command]
+async fn trust_certificate(state: tauri::State<'_, Mutex<State>>) -> Result<(), ErrorMsg> {
+  let mut state = state.lock().await;
+
+  let (addr, cert) = state
+    .untrusted
+    .take()
+    .ok_or("no untrusted certificate to trust")?;
+  let path = pins_path()?;
+  let mut pins = Pins::load(&path)?;
+  if pins.pin(&addr, cert.fingerprint()) {
+    pins.save(&path)?;
+  }
+  Ok(())
+}
+
+#[tauri::command]
+fn load_account() -> Result<Option<Account>, ErrorMsg> {
+  let path = account_path()?;
+  if !path.exists() {
+    return Ok(None);
+  }
+  Ok(Some(Account::load(&path)?))
 }
 
 #[tauri::command]
@@ -154,6 +575,7 @@ async fn quit(state: tauri::State<'_, Mutex<State>>) -> Result<String, ErrorMsg>
   let mut state = state.lock().await;
 
   let connection = state.connection.take().ok_or("no pop3 server connection")?;
+  state.mails.clear();
 
   let msg = connection.quit().await?;
 
@@ -165,13 +587,32 @@ fn main() {
     .manage(Mutex::new(State::default()))
     .invoke_handler(tauri::generate_handler![
       connect,
+      connection_info,
       user,
       pass,
+      auth_external,
       stat,
       list,
       retr,
+      list_attachments,
+      save_attachment,
+      open_attachment,
+      raw_source,
+      save_raw_source,
+      export_eml,
+      export_mbox,
+      import_mbox,
+      import_maildir,
+      list_folders,
+      list_folder,
+      read_folder_mail,
+      fetch_mail,
+      save_account,
+      load_account,
+      trust_certificate,
       quit,
       msg_command::user_msg,
+      msg_command::auth_external_msg,
       msg_command::pass_msg,
       msg_command::stat_msg,
       msg_command::list_msg,
@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Error};
+use pop3_client::email::Email;
+use std::{
+  collections::{BTreeMap, HashSet},
+  fs::OpenOptions,
+  io::{ErrorKind, Write},
+  path::{Path, PathBuf},
+};
+
+/// a local folder of mails, kept as one `<n>.eml` file per mail
+pub struct Folder {
+  dir: PathBuf,
+  /// sizes of the mails in the folder, by number
+  mails: BTreeMap<u64, u64>,
+  /// Message-IDs of the mails in the folder, only read once a mail is inserted
+  message_ids: Option<HashSet<String>>,
+}
+
+/// directory holding the local folders
+pub fn store_dir() -> Result<PathBuf, Error> {
+  let data_dir = tauri::api::path::data_dir().ok_or_else(|| anyhow!("no data directory"))?;
+  Ok(data_dir.join("pop3-client").join("folders"))
+}
+
+/// names of the folders in the local store
+pub fn list_local() -> Result<Vec<String>, Error> {
+  let mut names = vec![];
+  let entries = match std::fs::read_dir(store_dir()?) {
+    Ok(entries) => entries,
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(names),
+    Err(err) => return Err(err.into()),
+  };
+  for entry in entries {
+    let entry = entry?;
+    if entry.file_type()?.is_dir() {
+      names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+  }
+  names.sort();
+  Ok(names)
+}
+
+impl Folder {
+  /// open the folder `name` in the local store, creating it if needed
+  pub fn open_local(name: &str) -> Result<Self, Error> {
+    // only keep the last component, so a crafted name cannot escape the store
+    let name = Path::new(name)
+      .file_name()
+      .ok_or_else(|| anyhow!("invalid folder name"))?;
+    Self::open(store_dir()?.join(name))
+  }
+
+  pub fn open(dir: PathBuf) -> Result<Self, Error> {
+    std::fs::create_dir_all(&dir)?;
+    let mut mails = BTreeMap::new();
+    for entry in std::fs::read_dir(&dir)? {
+      let entry = entry?;
+      let path = entry.path();
+      if path.extension().is_none_or(|ext| ext != "eml") {
+        continue;
+      }
+      if let Some(n) = path
+        .file_stem()
+        .and_then(|stem| stem.to_str()?.parse::<u64>().ok())
+      {
+        mails.insert(n, entry.metadata()?.len());
+      }
+    }
+    Ok(Self {
+      dir,
+      mails,
+      message_ids: None,
+    })
+  }
+
+  fn path(&self, n: u64) -> PathBuf {
+    self.dir.join(format!("{}.eml", n))
+  }
+
+  /// numbers and sizes of the mails in the folder, in the order they were inserted
+  pub fn list(&self) -> Vec<(u64, u64)> {
+    self.mails.iter().map(|(&n, &size)| (n, size)).collect()
+  }
+
+  /// raw mail `n`
+  pub fn read(&self, n: u64) -> Result<Vec<u8>, Error> {
+    if !self.mails.contains_key(&n) {
+      return Err(anyhow!("no mail {} in the folder", n));
+    }
+    Ok(std::fs::read(self.path(n))?)
+  }
+
+  fn message_ids(&mut self) -> Result<&mut HashSet<String>, Error> {
+    if self.message_ids.is_none() {
+      let mut message_ids = HashSet::new();
+      for &n in self.mails.keys() {
+        let mail = std::fs::read(self.path(n))?;
+        if let Ok(email) = Email::try_from(&mail[..]) {
+          if let Some(id) = email.message_id() {
+            message_ids.insert(id.to_string());
+          }
+        }
+      }
+      self.message_ids = Some(message_ids);
+    }
+    Ok(self.message_ids.get_or_insert_with(HashSet::new))
+  }
+
+  /// store `mail` unless a mail with the same Message-ID is already in the folder, returning the
+  /// path of the new file
+  pub fn insert(&mut self, mail: &[u8]) -> Result<Option<PathBuf>, Error> {
+    let email = Email::try_from(mail)?;
+    let id = email.message_id().map(str::to_string);
+    if let Some(id) = &id {
+      if self.message_ids()?.contains(id) {
+        return Ok(None);
+      }
+    }
+    let n = self.mails.keys().next_back().map_or(0, |n| n + 1);
+    let path = self.path(n);
+    OpenOptions::new()
+      .write(true)
+      .create_new(true)
+      .open(&path)?
+      .write_all(mail)?;
+    // only recorded once written, so that a failed write does not hide the mail from a retry
+    self.mails.insert(n, mail.len() as u64);
+    if let Some(id) = id {
+      self.message_ids()?.insert(id);
+    }
+    Ok(Some(path))
+  }
+}
+
+#[test]
+fn test_folder_duplicates() {
+  let dir = std::env::temp_dir().join(format!("pop3-client-test-folder-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+
+  let mut folder = Folder::open(dir.clone()).unwrap();
+  let mail = b"Message-ID: <1@example.com>\r\nSubject: hi\r\n\r\nhello\r\n";
+  assert!(folder.insert(mail).unwrap().is_some());
+  assert!(folder.insert(mail).unwrap().is_none());
+  // mails without a Message-ID cannot be told apart
+  let anonymous = b"Subject: hi\r\n\r\nhello\r\n";
+  assert!(folder.insert(anonymous).unwrap().is_some());
+  assert!(folder.insert(anonymous).unwrap().is_some());
+
+  // the Message-IDs are picked up again when reopening
+  let mut folder = Folder::open(dir.clone()).unwrap();
+  assert!(folder.insert(mail).unwrap().is_none());
+  assert_eq!(
+    folder
+      .insert(b"Message-ID: <2@example.com>\r\n\r\n")
+      .unwrap(),
+    Some(dir.join("3.eml"))
+  );
+  assert_eq!(folder.list().len(), 4);
+  assert_eq!(folder.read(0).unwrap(), mail);
+  assert!(folder.read(4).is_err());
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,74 @@
+import { FC } from "react";
+
+import Button from "@mui/material/Button";
+import Dialog from "@mui/material/Dialog";
+import DialogActions from "@mui/material/DialogActions";
+import DialogContent from "@mui/material/DialogContent";
+import DialogContentText from "@mui/material/DialogContentText";
+import DialogTitle from "@mui/material/DialogTitle";
+import Typography from "@mui/material/Typography";
+
+export type CertificateInfo = {
+  subject: string;
+  issuer: string;
+  subject_alt_names: string[];
+  not_before: number;
+  not_after: number;
+  fingerprint: string;
+};
+
+export type TlsCertificatePrompt = {
+  reason: string;
+  chain: CertificateInfo[];
+};
+
+const formatTime = (secs: number) => new Date(secs * 1000).toLocaleString();
+
+type CertificatePromptProps = {
+  prompt: TlsCertificatePrompt | null;
+  onClose: (trust: boolean) => void;
+};
+
+const CertificatePrompt: FC<CertificatePromptProps> = ({ prompt, onClose }) => {
+  const leaf = prompt?.chain[0];
+
+  return (
+    <Dialog open={!!prompt} onClose={() => onClose(false)}>
+      <DialogTitle>无法验证服务器证书</DialogTitle>
+      <DialogContent>
+        <DialogContentText>
+          {prompt?.reason}。请确认以下证书属于该服务器，信任后将记住该证书的指纹。
+        </DialogContentText>
+        {leaf && (
+          <>
+            <Typography variant="body2" sx={{ mt: 2 }}>
+              主体：{leaf.subject}
+            </Typography>
+            <Typography variant="body2">颁发者：{leaf.issuer}</Typography>
+            <Typography variant="body2">
+              有效期：{formatTime(leaf.not_before)} 至{" "}
+              {formatTime(leaf.not_after)}
+            </Typography>
+            <Typography variant="body2">
+              备用名称：{leaf.subject_alt_names.join(", ")}
+            </Typography>
+            <Typography
+              variant="body2"
+              sx={{ fontFamily: "monospace", wordBreak: "break-all" }}
+            >
+              SHA-256：{leaf.fingerprint}
+            </Typography>
+          </>
+        )}
+      </DialogContent>
+      <DialogActions>
+        <Button onClick={() => onClose(false)}>取消</Button>
+        <Button color="warning" onClick={() => onClose(true)}>
+          信任此证书
+        </Button>
+      </DialogActions>
+    </Dialog>
+  );
+};
+
+export default CertificatePrompt;
@@ -1,4 +1,4 @@
-import { FC } from "react";
+import { FC, useRef, useState } from "react";
 import { useForm, Controller } from "react-hook-form";
 
 import { invoke } from "@tauri-apps/api";
@@ -8,11 +8,16 @@ import checkError from "utils/checkError";
 import LoginState from "states/LoginState";
 import ConsoleState from "states/ConsoleState";
 
+import CertificatePrompt, {
+  TlsCertificatePrompt,
+} from "components/CertificatePrompt";
+
 import Avatar from "@mui/material/Avatar";
 import Button from "@mui/material/Button";
 import TextField from "@mui/material/TextField";
 import FormControlLabel from "@mui/material/FormControlLabel";
 import Checkbox from "@mui/material/Checkbox";
+import MenuItem from "@mui/material/MenuItem";
 import Box from "@mui/material/Box";
 import Email from "@mui/icons-material/Email";
 import Typography from "@mui/material/Typography";
@@ -23,7 +28,8 @@ type LoginData = {
   addr: string;
   username: string;
   password: string;
-  withTls: boolean;
+  security: "tls" | "starttls" | "plain";
+  external: boolean;
 };
 
 const Login: FC = () => {
@@ -34,8 +40,20 @@ const Login: FC = () => {
     handleSubmit,
     setError,
     control,
+    watch,
     formState: { isSubmitting },
   } = useForm<LoginData>();
+  // logging in with the client certificate needs no password, and the user name is optional
+  const external = watch("external", false);
+
+  // an untrusted server certificate waiting for the user to trust it or not
+  const [prompt, setPrompt] = useState<TlsCertificatePrompt | null>(null);
+  const answerPrompt = useRef<(trust: boolean) => void>();
+  const askTrust = (prompt: TlsCertificatePrompt) =>
+    new Promise<boolean>((resolve) => {
+      answerPrompt.current = resolve;
+      setPrompt(prompt);
+    });
 
   return (
     <Container
@@ -64,12 +82,28 @@ const Login: FC = () => {
         <Box
           component="form"
           onSubmit={handleSubmit(async (data) => {
-            // connect remote
-            try {
-              await invoke("connect", {
+            // connect remote, retrying once the user trusts the certificate
+            const connect = () =>
+              invoke("connect", {
                 addr: data.addr,
-                withTls: data.withTls,
+                security: data.security,
               });
+            try {
+              try {
+                await connect();
+              } catch (err) {
+                const prompt = (err as any)
+                  .tls_certificate_prompt as TlsCertificatePrompt | null;
+                if (!prompt || prompt.chain.length === 0) {
+                  throw err;
+                }
+                if (!(await askTrust(prompt))) {
+                  throw err;
+                }
+                // the backend pins the certificate it was just shown
+                await invoke("trust_certificate");
+                await connect();
+              }
               logInfo("network", `${data.addr} 成功连接`);
             } catch (err) {
               checkError(err, (message) =>
@@ -79,34 +113,73 @@ const Login: FC = () => {
               return;
             }
 
-            // user command
+            // a tunnel command of the account may start the session logged in
+            let preauthenticated = false;
             try {
-              const payload = {
-                name: data.username,
-              };
-              logInfo("command", await invoke("user_msg", payload));
-              logInfo("response", await invoke("user", payload));
+              ({ preauthenticated } = (await invoke("connection_info")) as {
+                preauthenticated: boolean;
+              });
             } catch (err) {
-              checkError(err, (message) =>
-                setError("username", { type: "network", message })
-              );
-              logError("response", err);
-              return;
+              logError("other", err);
+            }
+
+            if (preauthenticated) {
+              logInfo("network", `${data.addr} 已由隧道命令登录`);
+            } else if (data.external) {
+              // SASL EXTERNAL, relying on the client certificate of the account
+              try {
+                const payload = {
+                  authzid: data.username || null,
+                };
+                logInfo("command", await invoke("auth_external_msg", payload));
+                logInfo("response", await invoke("auth_external", payload));
+              } catch (err) {
+                checkError(err, (message) =>
+                  setError("username", { type: "network", message })
+                );
+                logError("response", err);
+                return;
+              }
+            } else {
+              // user command
+              try {
+                const payload = {
+                  name: data.username,
+                };
+                logInfo("command", await invoke("user_msg", payload));
+                logInfo("response", await invoke("user", payload));
+              } catch (err) {
+                checkError(err, (message) =>
+                  setError("username", { type: "network", message })
+                );
+                logError("response", err);
+                return;
+              }
+
+              // user command
+              try {
+                const payload = {
+                  secret: data.password,
+                };
+                logInfo(
+                  "command",
+                  `PASS ${"*".repeat(data.password.length)}`
+                );
+                logInfo("response", await invoke("pass", payload));
+              } catch (err) {
+                checkError(err, (message) =>
+                  setError("password", { type: "network", message })
+                );
+                logError("response", err);
+                return;
+              }
             }
 
-            // user command
+            // remember the account for the next login and pop3-cli
             try {
-              const payload = {
-                secret: data.password,
-              };
-              logInfo("command", `PASS ${"*".repeat(data.password.length)}`);
-              logInfo("response", await invoke("pass", payload));
+              await invoke("save_account", { savePassword: false });
             } catch (err) {
-              checkError(err, (message) =>
-                setError("password", { type: "network", message })
-              );
-              logError("response", err);
-              return;
+              logError("other", err);
             }
 
             setAddr(data.addr);
@@ -149,7 +222,7 @@ const Login: FC = () => {
             name="username"
             control={control}
             defaultValue=""
-            rules={{ required: "请输入邮箱用户名" }}
+            rules={{ required: !external && "请输入邮箱用户名" }}
             render={({
               field: { onChange, onBlur, value, ref },
               fieldState: { error },
@@ -158,9 +231,9 @@ const Login: FC = () => {
               <TextField
                 id="username"
                 margin="normal"
-                required
+                required={!external}
                 fullWidth
-                label="邮箱用户名"
+                label={external ? "邮箱用户名（可选）" : "邮箱用户名"}
                 autoComplete="username"
                 error={!!error}
                 helperText={error && error.message}
@@ -177,7 +250,7 @@ const Login: FC = () => {
             name="password"
             control={control}
             defaultValue=""
-            rules={{ required: "请输入密码" }}
+            rules={{ required: !external && "请输入密码" }}
             render={({
               field: { onChange, onBlur, value, ref },
               fieldState: { error },
@@ -186,7 +259,7 @@ const Login: FC = () => {
               <TextField
                 id="password"
                 margin="normal"
-                required
+                required={!external}
                 fullWidth
                 label="密码"
                 type="password"
@@ -203,14 +276,37 @@ const Login: FC = () => {
           />
 
           <Controller
-            name="withTls"
+            name="security"
+            control={control}
+            defaultValue="tls"
+            render={({ field: { onChange, onBlur, value, ref } }) => (
+              <TextField
+                select
+                margin="normal"
+                fullWidth
+                label="连接安全"
+                disabled={isSubmitting}
+                value={value}
+                onChange={onChange}
+                onBlur={onBlur}
+                inputRef={ref}
+              >
+                <MenuItem value="tls">TLS</MenuItem>
+                <MenuItem value="starttls">STARTTLS（STLS）</MenuItem>
+                <MenuItem value="plain">明文（不加密）</MenuItem>
+              </TextField>
+            )}
+          />
+
+          <Controller
+            name="external"
             control={control}
-            defaultValue={true}
+            defaultValue={false}
             render={({ field: { onChange, onBlur, value, ref } }) => (
               <FormControlLabel
                 control={
                   <Checkbox
-                    value="tls"
+                    value="external"
                     color="primary"
                     disabled={isSubmitting}
                     checked={value}
@@ -219,7 +315,7 @@ const Login: FC = () => {
                     inputRef={ref}
                   />
                 }
-                label="启用 TLS"
+                label="使用客户端证书登录"
               />
             )}
           />
@@ -250,6 +346,13 @@ const Login: FC = () => {
           By HareInWeed
         </Typography>
       </Box>
+      <CertificatePrompt
+        prompt={prompt}
+        onClose={(trust) => {
+          setPrompt(null);
+          answerPrompt.current?.(trust);
+        }}
+      />
     </Container>
   );
 };
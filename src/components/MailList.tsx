@@ -29,6 +29,8 @@ import CircularProgress from "@mui/material/CircularProgress";
 import Email from "@mui/icons-material/Email";
 import Article from "@mui/icons-material/Article";
 import EmailOutlined from "@mui/icons-material/EmailOutlined";
+import Folder from "@mui/icons-material/Folder";
+import FolderOpen from "@mui/icons-material/FolderOpen";
 import Logout from "@mui/icons-material/Logout";
 import Refresh from "@mui/icons-material/Refresh";
 
@@ -52,7 +54,29 @@ interface MailInfo {
   bytes: number;
 }
 
-type MailPartData =
+interface AddressData {
+  name: string | null;
+  email: string;
+  group: string | null;
+}
+
+const formatAddresses = (addresses: AddressData[]) =>
+  addresses
+    .map(({ name, email }) =>
+      name ? (email ? `${name} <${email}>` : name) : email
+    )
+    .join(", ");
+
+interface AttachmentData {
+  part: number;
+  filename: string | null;
+  mimetype: string;
+  content_id: string | null;
+  disposition: string;
+  size: number;
+}
+
+type MailPartContent =
   | {
       Text: string;
     }
@@ -60,16 +84,29 @@ type MailPartData =
       Html: string;
     }
   | {
-      Bin: number[];
+      Bin: AttachmentData;
     };
 
+interface MailPartData {
+  path: number[];
+  mimetype: string;
+  content: MailPartContent;
+}
+
 interface MailData {
   raw: string;
+  message_id: string | null;
   subject: string;
-  from: string;
-  to: string;
+  from: AddressData[];
+  sender: AddressData[];
+  reply_to: AddressData[];
+  to: AddressData[];
+  cc: AddressData[];
+  bcc: AddressData[];
   time: string;
+  date: { timestamp: number; offset: number } | null;
   parts: MailPartData[];
+  body: number | null;
 }
 
 interface MailCardProps {
@@ -80,24 +117,33 @@ const MailCard: FC<MailCardProps> = ({ mail }) => {
   const [tab, setTab] = useState("html-0");
   const [htmls, setHtmls] = useState<string[]>([]);
   const [texts, setTexts] = useState<string[]>([]);
-  const [bins, setBins] = useState<number[][]>([]);
+  const [bins, setBins] = useState<AttachmentData[]>([]);
 
   useEffect(() => {
     const htmls: string[] = [];
     const texts: string[] = [];
-    const bins: number[][] = [];
-    for (const part of mail.parts) {
+    const bins: AttachmentData[] = [];
+    let bodyTab = "raw";
+    mail.parts.forEach(({ content: part }, idx) => {
+      let partTab: string;
       if ("Html" in part) {
+        partTab = `html-${htmls.length}`;
         htmls.push(part.Html);
       } else if ("Text" in part) {
+        partTab = `text-${texts.length}`;
         texts.push(part.Text);
-      } else if ("Bin" in part) {
+      } else {
+        partTab = `bin-${bins.length}`;
         bins.push(part.Bin);
       }
-    }
+      if (idx === mail.body) {
+        bodyTab = partTab;
+      }
+    });
     setHtmls(htmls);
     setTexts(texts);
     setBins(bins);
+    setTab(bodyTab);
   }, [mail]);
 
   return (
@@ -124,13 +170,16 @@ const MailCard: FC<MailCardProps> = ({ mail }) => {
           {mail.subject}
         </Typography>
         <Typography variant="body2" component="div">
-          &emsp;由：{mail.from}
+          &emsp;由：{formatAddresses(mail.from)}
         </Typography>
         <Typography variant="body2" component="div">
-          发往：{mail.to}
+          发往：{formatAddresses(mail.to)}
         </Typography>
         <Typography variant="body2" component="div">
-          时间：{mail.time}
+          时间：
+          {mail.date
+            ? new Date(mail.date.timestamp * 1000).toLocaleString()
+            : mail.time}
         </Typography>
       </Box>
       <TabContext value={tab}>
@@ -208,7 +257,7 @@ const MailCard: FC<MailCardProps> = ({ mail }) => {
             key={`bin-${idx}`}
             sx={{ flexGrow: 1 }}
           >
-            {bin.length} bytes
+            {bin.filename ?? bin.mimetype}（{bin.size} bytes）
           </TabPanel>
         ))}
         <TabPanel
@@ -261,6 +310,9 @@ const MailList: FC = () => {
   const [mailData, setMailData] = useState<MailData | null>(null);
   const [fetching, setFetching] = useState(true);
   const [mailInfos, setMailInfos] = useState<MailInfo[]>([]);
+  const [folders, setFolders] = useState<string[]>([]);
+  const [openFolder, setOpenFolder] = useState<string | null>(null);
+  const [folderMailInfos, setFolderMailInfos] = useState<MailInfo[]>([]);
 
   useEffect(() => {
     (async () => {
@@ -300,6 +352,33 @@ const MailList: FC = () => {
     })();
   }, [listTrigger, setFetching, logInfo, logError]);
 
+  useEffect(() => {
+    (async () => {
+      try {
+        setFolders((await invoke("list_folders")) as string[]);
+      } catch (err) {
+        logError("other", err);
+      }
+    })();
+  }, [listTrigger, logError]);
+
+  useEffect(() => {
+    if (openFolder == null) {
+      setFolderMailInfos([]);
+      return;
+    }
+    (async () => {
+      try {
+        const mails = (await invoke("list_folder", {
+          folder: openFolder,
+        })) as [number, number][];
+        setFolderMailInfos(mails.map(([index, bytes]) => ({ index, bytes })));
+      } catch (err) {
+        logError("other", err);
+      }
+    })();
+  }, [openFolder, listTrigger, logError]);
+
   return (
     <>
       <AppBar
@@ -405,9 +484,13 @@ const MailList: FC = () => {
                       const [newMailData, msg] = (await invoke(
                         "retr",
                         payload
-                      )) as [MailData, string];
-                      setMailData(newMailData);
-                      logInfo("response", `${msg}\r\n${newMailData.raw}.\r\n`);
+                      )) as [Omit<MailData, "raw">, string];
+                      const raw = (await invoke(
+                        "raw_source",
+                        payload
+                      )) as string;
+                      setMailData({ ...newMailData, raw });
+                      logInfo("response", `${msg}\r\n${raw}.\r\n`);
                     } catch (err) {
                       logError("response", err);
                     }
@@ -424,6 +507,54 @@ const MailList: FC = () => {
               </ListItem>
             ))
           )}
+          {folders.length > 0 && (
+            <ListSubheader component="div" sx={{ lineHeight: "3em" }}>
+              本地文件夹
+            </ListSubheader>
+          )}
+          {folders.map((folder) => (
+            <div key={`folder-${folder}`}>
+              <ListItem disablePadding>
+                <ListItemButton
+                  onClick={() => {
+                    setOpenFolder(openFolder === folder ? null : folder);
+                  }}
+                >
+                  <ListItemIcon>
+                    {openFolder === folder ? <FolderOpen /> : <Folder />}
+                  </ListItemIcon>
+                  <ListItemText primary={folder} />
+                </ListItemButton>
+              </ListItem>
+              {openFolder === folder &&
+                folderMailInfos.map((mail) => (
+                  <ListItem disablePadding key={`${folder}-${mail.index}`}>
+                    <ListItemButton
+                      sx={{ pl: 4 }}
+                      onClick={async () => {
+                        try {
+                          const [newMailData, raw] = (await invoke(
+                            "read_folder_mail",
+                            { folder, id: mail.index }
+                          )) as [Omit<MailData, "raw">, string];
+                          setMailData({ ...newMailData, raw });
+                        } catch (err) {
+                          logError("other", err);
+                        }
+                      }}
+                    >
+                      <ListItemIcon>
+                        <EmailOutlined />
+                      </ListItemIcon>
+                      <ListItemText
+                        primary={`邮件 #${mail.index}`}
+                        secondary={`${mail.bytes} Bytes`}
+                      />
+                    </ListItemButton>
+                  </ListItem>
+                ))}
+            </div>
+          ))}
         </List>
         {mailData == null ? <MailPlaceholder /> : <MailCard mail={mailData} />}
       </Box>